@@ -0,0 +1,7 @@
+-- sessions for `session.store=postgres`
+CREATE TABLE IF NOT EXISTS security.sessions (
+    token         uuid        PRIMARY KEY,
+    personnel_nr  smallint    NOT NULL UNIQUE
+                              REFERENCES security.users (personnel_nr) ON DELETE CASCADE,
+    authenticated timestamptz NOT NULL
+);
@@ -0,0 +1,2 @@
+-- expired sessions are removed by `authenticated`
+CREATE INDEX IF NOT EXISTS sessions_authenticated_idx ON security.sessions (authenticated);
@@ -0,0 +1,3 @@
+-- last activity of session, for idle timeout
+ALTER TABLE security.sessions
+    ADD COLUMN IF NOT EXISTS last_seen timestamptz NOT NULL DEFAULT now();
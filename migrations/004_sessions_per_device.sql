@@ -0,0 +1,9 @@
+-- one session per device: several sessions of one user
+ALTER TABLE security.sessions DROP CONSTRAINT IF EXISTS sessions_personnel_nr_key;
+
+ALTER TABLE security.sessions
+    ADD COLUMN IF NOT EXISTS created    timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN IF NOT EXISTS user_agent text,
+    ADD COLUMN IF NOT EXISTS client_ip  text;
+
+CREATE INDEX IF NOT EXISTS sessions_personnel_nr_idx ON security.sessions (personnel_nr, created);
@@ -0,0 +1,8 @@
+-- session id, shown to the user instead of the token
+ALTER TABLE security.sessions ADD COLUMN IF NOT EXISTS id uuid;
+
+UPDATE security.sessions SET id = md5(token::text || random()::text)::uuid WHERE id IS NULL;
+
+ALTER TABLE security.sessions ALTER COLUMN id SET NOT NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS sessions_id_idx ON security.sessions (id);
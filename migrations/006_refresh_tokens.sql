@@ -0,0 +1,13 @@
+-- short-lived auth tokens, renewed with rotating refresh tokens
+ALTER TABLE security.sessions
+    ADD COLUMN IF NOT EXISTS token_issued timestamptz NOT NULL DEFAULT now();
+
+CREATE TABLE IF NOT EXISTS security.refresh_tokens (
+    token      uuid        PRIMARY KEY,
+    session_id uuid        NOT NULL REFERENCES security.sessions (id) ON DELETE CASCADE,
+    created    timestamptz NOT NULL DEFAULT now(),
+    -- used refresh tokens are kept to detect their reuse
+    used       timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_session_id_idx ON security.refresh_tokens (session_id);
@@ -0,0 +1,9 @@
+-- registered oauth clients; secret is hashed like user passwords
+CREATE TABLE IF NOT EXISTS security.oauth_clients (
+    client_id text        PRIMARY KEY,
+    name      text        NOT NULL,
+    salt      text        NOT NULL,
+    secret    text        NOT NULL,
+    disabled  boolean     NOT NULL DEFAULT false,
+    created   timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,10 @@
+-- audit of tokens revoked through /revoke
+CREATE TABLE IF NOT EXISTS security.token_revocations (
+    id           bigserial   PRIMARY KEY,
+    revoked      timestamptz NOT NULL DEFAULT now(),
+    session_id   uuid        NOT NULL,
+    personnel_nr smallint    NOT NULL,
+    token_type   text        NOT NULL,
+    -- client id, or `admin:<personnel nr>`
+    revoked_by   text        NOT NULL
+);
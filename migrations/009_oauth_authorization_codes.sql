@@ -0,0 +1,21 @@
+-- redirect URIs registered for oauth clients, matched exactly
+CREATE TABLE IF NOT EXISTS security.oauth_client_redirect_uris (
+    client_id    text NOT NULL REFERENCES security.oauth_clients (client_id) ON DELETE CASCADE,
+    redirect_uri text NOT NULL,
+    PRIMARY KEY (client_id, redirect_uri)
+);
+
+-- authorization codes of the authorization code flow with PKCE
+CREATE TABLE IF NOT EXISTS security.oauth_authorization_codes (
+    code           uuid        PRIMARY KEY,
+    client_id      text        NOT NULL REFERENCES security.oauth_clients (client_id) ON DELETE CASCADE,
+    personnel_nr   smallint    NOT NULL,
+    redirect_uri   text        NOT NULL,
+    -- S256 code challenge
+    code_challenge text        NOT NULL,
+    scope          text,
+    created        timestamptz NOT NULL DEFAULT now(),
+    expires        timestamptz NOT NULL,
+    -- codes are single use
+    used           timestamptz
+);
@@ -0,0 +1,40 @@
+-- service clients of the client credentials grant, with their own roles and resources
+ALTER TABLE security.oauth_clients
+    ADD COLUMN IF NOT EXISTS service boolean NOT NULL DEFAULT false;
+
+CREATE TABLE IF NOT EXISTS security.oauth_client_roles (
+    client_id text     NOT NULL REFERENCES security.oauth_clients (client_id) ON DELETE CASCADE,
+    role_id   smallint NOT NULL,
+    PRIMARY KEY (client_id, role_id)
+);
+
+CREATE TABLE IF NOT EXISTS security.oauth_client_resources (
+    client_id               text     NOT NULL REFERENCES security.oauth_clients (client_id) ON DELETE CASCADE,
+    resource_id             smallint NOT NULL,
+    with_write_or_execution boolean  NOT NULL DEFAULT false,
+    PRIMARY KEY (client_id, resource_id)
+);
+
+-- same shape as security.v_user_roles and security.v_user_resources
+CREATE OR REPLACE VIEW security.v_client_roles AS
+    SELECT cr.client_id, r.role_id, r.role_name
+    FROM security.oauth_client_roles cr
+    JOIN security.roles r ON r.role_id = cr.role_id;
+
+CREATE OR REPLACE VIEW security.v_client_resources AS
+    SELECT cr.client_id, r.resource_id, r.resource_name, cr.with_write_or_execution
+    FROM security.oauth_client_resources cr
+    JOIN security.resources r ON r.resource_id = cr.resource_id;
+
+-- sessions of service clients belong to no user
+ALTER TABLE security.sessions
+    ADD COLUMN IF NOT EXISTS client_id text REFERENCES security.oauth_clients (client_id) ON DELETE CASCADE;
+ALTER TABLE security.sessions
+    ALTER COLUMN personnel_nr DROP NOT NULL;
+ALTER TABLE security.sessions
+    DROP CONSTRAINT IF EXISTS sessions_owner_check;
+ALTER TABLE security.sessions
+    ADD CONSTRAINT sessions_owner_check CHECK ((personnel_nr IS NULL) <> (client_id IS NULL));
+
+ALTER TABLE security.token_revocations
+    ALTER COLUMN personnel_nr DROP NOT NULL;
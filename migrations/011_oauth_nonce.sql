@@ -0,0 +1,3 @@
+-- OpenID Connect nonce, returned in the id_token
+ALTER TABLE security.oauth_authorization_codes
+    ADD COLUMN IF NOT EXISTS nonce text;
@@ -0,0 +1,52 @@
+-- notifications on channel `security_changes`, consumed by the server to invalidate sessions:
+--   revoke:<personnel nr>       user dismissed, disabled or deleted
+--   permissions:<personnel nr>  roles or resources of user changed
+
+CREATE OR REPLACE FUNCTION security.notify_user_changed() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM pg_notify('security_changes', 'revoke:' || OLD.personnel_nr);
+    ELSIF (NEW.account_disabled AND NOT OLD.account_disabled)
+        OR (NEW.date_dismiss IS NOT NULL AND OLD.date_dismiss IS NULL) THEN
+        PERFORM pg_notify('security_changes', 'revoke:' || NEW.personnel_nr);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS users_notify_changed ON security.users;
+CREATE TRIGGER users_notify_changed
+    AFTER UPDATE OR DELETE ON security.users
+    FOR EACH ROW EXECUTE FUNCTION security.notify_user_changed();
+
+-- works for any table with a `personnel_nr` column
+CREATE OR REPLACE FUNCTION security.notify_permissions_changed() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        PERFORM pg_notify('security_changes', 'permissions:' || (to_jsonb(OLD) ->> 'personnel_nr'));
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        PERFORM pg_notify('security_changes', 'permissions:' || (to_jsonb(NEW) ->> 'personnel_nr'));
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- role and resource grants behind security.v_user_roles and security.v_user_resources
+DO $$
+DECLARE
+    grants text;
+BEGIN
+    FOREACH grants IN ARRAY ARRAY['security.user_roles', 'security.user_resources'] LOOP
+        IF to_regclass(grants) IS NULL THEN
+            RAISE NOTICE 'table % not found, no permission notifications for it', grants;
+            CONTINUE;
+        END IF;
+        EXECUTE format('DROP TRIGGER IF EXISTS notify_permissions_changed ON %s', grants);
+        EXECUTE format(
+            'CREATE TRIGGER notify_permissions_changed AFTER INSERT OR UPDATE OR DELETE ON %s '
+            'FOR EACH ROW EXECUTE FUNCTION security.notify_permissions_changed()',
+            grants);
+    END LOOP;
+END;
+$$;
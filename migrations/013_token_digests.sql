@@ -0,0 +1,9 @@
+-- auth and refresh tokens are kept only as SHA-256 digests;
+-- tokens of the old format can't be converted, so open sessions are closed
+DELETE FROM security.sessions;
+
+ALTER TABLE security.sessions
+    ALTER COLUMN token TYPE bytea USING NULL;
+
+ALTER TABLE security.refresh_tokens
+    ALTER COLUMN token TYPE bytea USING NULL;
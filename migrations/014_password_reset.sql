@@ -0,0 +1,29 @@
+-- one-time codes of the forgot-password flow, kept only as SHA-256 digests
+CREATE TABLE IF NOT EXISTS security.password_reset_codes (
+    code         bytea       PRIMARY KEY,
+    personnel_nr smallint    NOT NULL,
+    created      timestamptz NOT NULL DEFAULT now(),
+    expires      timestamptz NOT NULL,
+    -- codes are single use; a new request invalidates the older codes of the user
+    used         timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS password_reset_codes_personnel_nr_idx
+    ON security.password_reset_codes (personnel_nr);
+
+-- outgoing mail; delivered by the outbox dispatcher, so that requests don't wait on mail servers
+CREATE TABLE IF NOT EXISTS security.mail_outbox (
+    id           bigserial   PRIMARY KEY,
+    recipient    text        NOT NULL,
+    subject      text        NOT NULL,
+    body         text        NOT NULL,
+    created      timestamptz NOT NULL DEFAULT now(),
+    sent         timestamptz,
+    attempts     integer     NOT NULL DEFAULT 0,
+    -- claimed mail is retried after this time, when delivery failed
+    next_attempt timestamptz NOT NULL DEFAULT now(),
+    last_error   text
+);
+
+CREATE INDEX IF NOT EXISTS mail_outbox_pending_idx
+    ON security.mail_outbox (next_attempt) WHERE sent IS NULL;
@@ -0,0 +1,4 @@
+-- PBKDF2 iterations of each password hash; hashes are upgraded to the configured cost on login.
+-- existing hashes were made with the former fixed cost
+ALTER TABLE security.users
+    ADD COLUMN IF NOT EXISTS password_iterations integer NOT NULL DEFAULT 1000;
@@ -0,0 +1,5 @@
+-- new passwords are Argon2id PHC strings, longer than the former base64 hashes;
+-- their salt is part of the string, so `salt` is left empty and `password_iterations` is 0
+ALTER TABLE security.users
+    ALTER COLUMN password TYPE text,
+    ALTER COLUMN salt TYPE text;
@@ -0,0 +1,15 @@
+-- earlier passwords of users, which can't be set again;
+-- the algorithm is given by the format of `password`: an Argon2id PHC string,
+-- or a legacy base64 PBKDF2-SHA256 hash with `salt` and `password_iterations`
+CREATE TABLE IF NOT EXISTS security.password_history (
+    id                  bigserial   PRIMARY KEY,
+    personnel_nr        smallint    NOT NULL,
+    salt                text        NOT NULL,
+    password            text        NOT NULL,
+    password_iterations integer     NOT NULL,
+    -- when the password was replaced
+    replaced            timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS password_history_personnel_nr_idx
+    ON security.password_history (personnel_nr, id);
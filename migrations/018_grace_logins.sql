@@ -0,0 +1,7 @@
+-- logins with an expired password, allowed until the password is changed
+ALTER TABLE security.users
+    ADD COLUMN IF NOT EXISTS grace_logins_used integer NOT NULL DEFAULT 0;
+
+-- sessions of grace logins may only change the password
+ALTER TABLE security.sessions
+    ADD COLUMN IF NOT EXISTS restricted boolean NOT NULL DEFAULT false;
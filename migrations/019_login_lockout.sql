@@ -0,0 +1,8 @@
+-- consecutive failed logins per account; the account is locked until `locked_until`,
+-- and the row is removed on successful login or unlock by an administrator
+CREATE TABLE IF NOT EXISTS security.login_failures (
+    personnel_nr smallint    PRIMARY KEY,
+    failures     integer     NOT NULL DEFAULT 0,
+    last_failure timestamptz NOT NULL DEFAULT now(),
+    locked_until timestamptz
+);
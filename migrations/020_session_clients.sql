@@ -0,0 +1,4 @@
+-- sessions opened for an oauth client by the authorization code grant;
+-- only that client may redeem their refresh tokens
+ALTER TABLE security.sessions
+    ADD COLUMN IF NOT EXISTS issued_to text REFERENCES security.oauth_clients (client_id) ON DELETE CASCADE;
@@ -0,0 +1,5 @@
+-- bodies of reset mail hold the code; they are cleared once the mail is sent or the code expires
+ALTER TABLE security.mail_outbox
+    ALTER COLUMN body DROP NOT NULL;
+ALTER TABLE security.mail_outbox
+    ADD COLUMN IF NOT EXISTS expires timestamptz;
@@ -0,0 +1,284 @@
+//! Administration of users, on the database of the server and with its configuration.
+//! Results are printed as JSON on stdout; failures as JSON on stderr, with exit code 1.
+
+use std::io::BufRead;
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+use deadpool_postgres::Client;
+use serde_json::{json, Value};
+
+use identity_server_rs::database::{
+    clear_failed_logins, delete_user_role, find_role_id, find_user_by_name, insert_user,
+    insert_user_role, list_users, load_password_history, load_user_roles, set_account_disabled,
+    set_password_expiration, update_user_password,
+};
+use identity_server_rs::errors::DatabaseError;
+use identity_server_rs::identity::{
+    Identity, LockoutPolicy, MemorySessionStore, PasswordHasher, PasswordPolicy, SessionPolicy,
+};
+use identity_server_rs::locale::Language;
+use identity_server_rs::{domain, setup};
+
+#[derive(Parser)]
+#[command(name = "identity-admin", about = "Manage users of the identity server")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List all users
+    ListUsers,
+    /// Show a user with their roles
+    ShowUser { personnel_nr: i16 },
+    /// Create a user; without --password-stdin a temporary password is generated
+    CreateUser {
+        personnel_nr: i16,
+        username: String,
+        #[arg(long)]
+        email: Option<String>,
+        #[arg(long)]
+        phone: Option<String>,
+        /// read the password from the first line of stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Set the password of a user, read from the first line of stdin
+    SetPassword { personnel_nr: i16 },
+    /// Replace the password of a user with a temporary one, to be changed at the next login
+    ResetPassword { personnel_nr: i16 },
+    /// Enable the account of a user
+    Enable { personnel_nr: i16 },
+    /// Disable the account of a user; the server revokes their sessions
+    Disable { personnel_nr: i16 },
+    /// End the lockout of a user after failed logins
+    Unlock { personnel_nr: i16 },
+    /// Set the password expiration date of a user (YYYY-MM-DD)
+    SetExpiration { personnel_nr: i16, date: NaiveDate },
+    /// Grant a role to a user
+    GrantRole { personnel_nr: i16, role: String },
+    /// Revoke a role from a user
+    RevokeRole { personnel_nr: i16, role: String },
+}
+
+/// what a command needs besides its arguments
+struct Admin {
+    client: Client,
+    identity: Identity,
+    config: setup::PasswordConfig,
+}
+
+#[actix_web::main]
+async fn main() {
+    env_logger::init_from_env(::env_logger::Env::default().default_filter_or("warn"));
+
+    let cli = Cli::parse();
+    let config = setup::load_config();
+
+    let pool = setup::create_db_pool(config.pg);
+    let client = match pool.get().await {
+        Ok(client) => client,
+        Err(err) => fail(database_failure(DatabaseError::PoolError(err))),
+    };
+    let password_hasher = PasswordHasher::new(&config.password);
+    // sessions live in the server; the admin only needs the password rules
+    let identity = Identity::new(
+        Arc::new(MemorySessionStore::new()),
+        SessionPolicy::new(&config.session),
+        None,
+        config.admin_role,
+        PasswordPolicy::new(&config.password, &password_hasher),
+        password_hasher,
+        LockoutPolicy::new(&config.lockout),
+    );
+    let admin = Admin {
+        client,
+        identity,
+        config: config.password,
+    };
+
+    match run(&admin, cli.command).await {
+        Ok(output) => println!("{}", output),
+        Err(failure) => fail(failure),
+    }
+}
+
+fn fail(failure: Value) -> ! {
+    eprintln!("{}", failure);
+    std::process::exit(1);
+}
+
+async fn run(admin: &Admin, command: Command) -> Result<Value, Value> {
+    let client = &admin.client;
+    match command {
+        Command::ListUsers => {
+            let users = list_users(client).await.map_err(database_failure)?;
+            Ok(json!(users))
+        }
+        Command::ShowUser { personnel_nr } => {
+            let user = find_user(client, personnel_nr).await?;
+            let roles = load_user_roles(client, personnel_nr)
+                .await
+                .map_err(database_failure)?;
+            Ok(json!({ "user": domain::UserAccount::from(user), "roles": roles }))
+        }
+        Command::CreateUser {
+            personnel_nr,
+            username,
+            email,
+            phone,
+            password_stdin,
+        } => {
+            let mut user = domain::User {
+                personnel_nr,
+                salt: String::new(),
+                password: String::new(),
+                password_iterations: 0,
+                password_expiration_date: chrono::Utc::now().date_naive(),
+                username,
+                account_disabled: false,
+                date_dismiss: None,
+                telefon: phone,
+                email,
+            };
+            let (temporary_password, change) = if password_stdin {
+                let password = read_password()?;
+                let change = admin
+                    .identity
+                    .set_password(&user, &password, &[], Language::default())
+                    .map_err(|err| json!(err))?;
+                (None, change)
+            } else {
+                let (password, change) = admin.identity.temporary_password(&user);
+                (Some(password), change)
+            };
+            user.salt = change.salt;
+            user.password = change.password;
+            user.password_iterations = change.password_iterations;
+            user.password_expiration_date = change.password_expiration_date;
+
+            if !insert_user(client, &user).await.map_err(database_failure)? {
+                return Err(failure("user_exists", "user already exists"));
+            }
+            Ok(json!({
+                "user": domain::UserAccount::from(user),
+                "temporary_password": temporary_password,
+            }))
+        }
+        Command::SetPassword { personnel_nr } => {
+            let user = find_user(client, personnel_nr).await?;
+            let password = read_password()?;
+            let history_depth = admin.config.history_depth as i64;
+            let history = load_password_history(client, personnel_nr, history_depth)
+                .await
+                .map_err(database_failure)?;
+            let change = admin
+                .identity
+                .set_password(&user, &password, &history, Language::default())
+                .map_err(|err| json!(err))?;
+            update_user_password(client, &change, history_depth)
+                .await
+                .map_err(database_failure)?;
+            Ok(json!({
+                "personnel_nr": personnel_nr,
+                "password_expiration_date": change.password_expiration_date,
+            }))
+        }
+        Command::ResetPassword { personnel_nr } => {
+            let user = find_user(client, personnel_nr).await?;
+            let (password, change) = admin.identity.temporary_password(&user);
+            update_user_password(client, &change, admin.config.history_depth as i64)
+                .await
+                .map_err(database_failure)?;
+            Ok(json!({ "personnel_nr": personnel_nr, "temporary_password": password }))
+        }
+        Command::Enable { personnel_nr } => set_disabled(client, personnel_nr, false).await,
+        Command::Disable { personnel_nr } => set_disabled(client, personnel_nr, true).await,
+        Command::Unlock { personnel_nr } => {
+            let unlocked = clear_failed_logins(client, personnel_nr)
+                .await
+                .map_err(database_failure)?;
+            Ok(json!({ "personnel_nr": personnel_nr, "changed": unlocked }))
+        }
+        Command::SetExpiration { personnel_nr, date } => {
+            if !set_password_expiration(client, personnel_nr, date)
+                .await
+                .map_err(database_failure)?
+            {
+                return Err(user_not_found(personnel_nr));
+            }
+            Ok(json!({ "personnel_nr": personnel_nr, "password_expiration_date": date }))
+        }
+        Command::GrantRole { personnel_nr, role } => {
+            find_user(client, personnel_nr).await?;
+            let role_id = find_role(client, &role).await?;
+            let granted = insert_user_role(client, personnel_nr, role_id)
+                .await
+                .map_err(database_failure)?;
+            Ok(json!({ "personnel_nr": personnel_nr, "role": role, "changed": granted }))
+        }
+        Command::RevokeRole { personnel_nr, role } => {
+            let role_id = find_role(client, &role).await?;
+            let revoked = delete_user_role(client, personnel_nr, role_id)
+                .await
+                .map_err(database_failure)?;
+            Ok(json!({ "personnel_nr": personnel_nr, "role": role, "changed": revoked }))
+        }
+    }
+}
+
+async fn set_disabled(client: &Client, personnel_nr: i16, disabled: bool) -> Result<Value, Value> {
+    if !set_account_disabled(client, personnel_nr, disabled)
+        .await
+        .map_err(database_failure)?
+    {
+        return Err(user_not_found(personnel_nr));
+    }
+    Ok(json!({ "personnel_nr": personnel_nr, "account_disabled": disabled }))
+}
+
+async fn find_user(client: &Client, personnel_nr: i16) -> Result<domain::User, Value> {
+    find_user_by_name(client, personnel_nr)
+        .await
+        .map_err(database_failure)?
+        .ok_or_else(|| user_not_found(personnel_nr))
+}
+
+async fn find_role(client: &Client, role: &str) -> Result<i16, Value> {
+    find_role_id(client, role)
+        .await
+        .map_err(database_failure)?
+        .ok_or_else(|| failure("role_not_found", format!("no role named {}", role)))
+}
+
+/// first line of stdin, without the line break
+fn read_password() -> Result<String, Value> {
+    let mut line = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|err| failure("stdin", err))?;
+    let password = line.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(failure("stdin", "no password on stdin"));
+    }
+    Ok(password.to_owned())
+}
+
+fn failure(error: &str, message: impl std::fmt::Display) -> Value {
+    json!({ "error": error, "message": message.to_string() })
+}
+
+fn database_failure(err: DatabaseError) -> Value {
+    failure("database", err)
+}
+
+fn user_not_found(personnel_nr: i16) -> Value {
+    failure(
+        "user_not_found",
+        format!("no user with personnel nr {}", personnel_nr),
+    )
+}
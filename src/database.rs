@@ -1,4 +1,6 @@
+use chrono::{DateTime, NaiveDate, Utc};
 use deadpool_postgres::Client;
+use uuid::Uuid;
 
 use crate::{domain, errors::DatabaseError};
 
@@ -19,7 +21,7 @@ pub async fn find_user_by_name(
     let stmt = client
         .prepare(
             "SELECT personnel_nr, salt, password, password_expiration_date, \
-            username, account_disabled, date_dismiss, telefon, email \
+            username, account_disabled, date_dismiss, telefon, email, password_iterations \
         FROM security.users \
         WHERE personnel_nr = $1",
         )
@@ -32,6 +34,105 @@ pub async fn find_user_by_name(
     Ok(user)
 }
 
+/// returns false if there is no such user; the replaced password goes to the history,
+/// which keeps the newest `history_depth` passwords of the user
+pub async fn update_user_password(
+    client: &Client,
+    change: &domain::PasswordChange,
+    history_depth: i64,
+) -> Result<bool, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "WITH replaced AS ( \
+                INSERT INTO security.password_history \
+                (personnel_nr, salt, password, password_iterations) \
+                SELECT personnel_nr, salt, password, password_iterations \
+                FROM security.users WHERE personnel_nr = $1 \
+            ) \
+            UPDATE security.users \
+            SET salt = $2, password = $3, password_iterations = $4, password_expiration_date = $5, \
+            grace_logins_used = 0 \
+            WHERE personnel_nr = $1",
+        )
+        .await?;
+
+    let count = client
+        .execute(
+            &stmt,
+            &[
+                &change.personnel_nr,
+                &change.salt,
+                &change.password,
+                &change.password_iterations,
+                &change.password_expiration_date,
+            ],
+        )
+        .await?;
+
+    let trim = client
+        .prepare(
+            "DELETE FROM security.password_history \
+            WHERE personnel_nr = $1 AND id NOT IN ( \
+                SELECT id FROM security.password_history WHERE personnel_nr = $1 \
+                ORDER BY id DESC LIMIT $2 \
+            )",
+        )
+        .await?;
+    client
+        .execute(&trim, &[&change.personnel_nr, &history_depth])
+        .await?;
+
+    Ok(count > 0)
+}
+
+/// earlier passwords of the user, newest first
+pub async fn load_password_history(
+    client: &Client,
+    personnel_nr: i16,
+    limit: i64,
+) -> Result<Vec<domain::PasswordHistoryEntry>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "SELECT salt, password, password_iterations FROM security.password_history \
+            WHERE personnel_nr = $1 \
+            ORDER BY id DESC LIMIT $2",
+        )
+        .await?;
+
+    let rows = client.query(&stmt, &[&personnel_nr, &limit]).await?;
+    Ok(rows.into_iter().map(|r| r.into()).collect())
+}
+
+/// replaces the hash of an unchanged password, keeping its expiration date;
+/// returns false if the password was changed meanwhile
+pub async fn rehash_user_password(
+    client: &Client,
+    change: &domain::PasswordChange,
+    previous_password: &str,
+) -> Result<bool, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "UPDATE security.users \
+            SET salt = $2, password = $3, password_iterations = $4 \
+            WHERE personnel_nr = $1 AND password = $5",
+        )
+        .await?;
+
+    let count = client
+        .execute(
+            &stmt,
+            &[
+                &change.personnel_nr,
+                &change.salt,
+                &change.password,
+                &change.password_iterations,
+                &previous_password,
+            ],
+        )
+        .await?;
+    Ok(count > 0)
+}
+
 pub async fn load_user_roles(
     client: &Client,
     personnel_nr: i16,
@@ -61,3 +162,783 @@ pub async fn load_user_resources(
     let roles = result.into_iter().map(|r| r.into()).collect();
     Ok(roles)
 }
+
+pub async fn load_client_roles(
+    client: &Client,
+    client_id: &str,
+) -> Result<Vec<domain::UserRole>, DatabaseError> {
+    let stmt = client
+        .prepare("SELECT role_id, role_name FROM security.v_client_roles WHERE client_id = $1")
+        .await?;
+
+    let result = client.query(&stmt, &[&client_id]).await?;
+
+    let roles = result.into_iter().map(|r| r.into()).collect();
+    Ok(roles)
+}
+
+pub async fn load_client_resources(
+    client: &Client,
+    client_id: &str,
+) -> Result<Vec<domain::UserResource>, DatabaseError> {
+    let stmt = client
+        .prepare("SELECT resource_id, resource_name, with_write_or_execution FROM security.v_client_resources WHERE client_id = $1")
+        .await?;
+
+    let result = client.query(&stmt, &[&client_id]).await?;
+
+    let resources = result.into_iter().map(|r| r.into()).collect();
+    Ok(resources)
+}
+
+pub async fn insert_session(
+    client: &Client,
+    session: &domain::Session,
+) -> Result<(), DatabaseError> {
+    let stmt = client
+        .prepare(
+            "INSERT INTO security.sessions \
+            (id, token, personnel_nr, created, authenticated, last_seen, user_agent, client_ip, \
+            token_issued, client_id, restricted, issued_to) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+        )
+        .await?;
+
+    client
+        .execute(
+            &stmt,
+            &[
+                &session.id,
+                &session.token,
+                &session.personnel_nr,
+                &session.created,
+                &session.authenticated,
+                &session.last_seen,
+                &session.user_agent,
+                &session.client_ip,
+                &session.token_issued,
+                &session.client_id,
+                &session.restricted,
+                &session.issued_to,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// keep only `keep` newest sessions of user;
+/// returns count of removed sessions
+pub async fn delete_oldest_sessions(
+    client: &Client,
+    personnel_nr: i16,
+    keep: i64,
+) -> Result<u64, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "DELETE FROM security.sessions WHERE token IN ( \
+                SELECT token FROM security.sessions WHERE personnel_nr = $1 AND client_id IS NULL \
+                ORDER BY created DESC OFFSET $2)",
+        )
+        .await?;
+
+    let count = client.execute(&stmt, &[&personnel_nr, &keep]).await?;
+    Ok(count)
+}
+
+pub async fn find_session(
+    client: &Client,
+    token: &[u8],
+) -> Result<Option<domain::Session>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "SELECT id, token, personnel_nr, created, authenticated, last_seen, \
+            user_agent, client_ip, token_issued, client_id, restricted, issued_to \
+        FROM security.sessions \
+        WHERE token = $1",
+        )
+        .await?;
+
+    let result = client.query_opt(&stmt, &[&token]).await?;
+
+    let session = result.map(|r| r.into());
+    Ok(session)
+}
+
+pub async fn find_active_sessions_of_user(
+    client: &Client,
+    personnel_nr: i16,
+    authenticated_after: DateTime<Utc>,
+    last_seen_after: Option<DateTime<Utc>>,
+) -> Result<Vec<domain::Session>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "SELECT id, token, personnel_nr, created, authenticated, last_seen, \
+            user_agent, client_ip, token_issued, client_id, restricted, issued_to \
+        FROM security.sessions \
+        WHERE personnel_nr = $1 AND client_id IS NULL AND authenticated >= $2 AND ($3::timestamptz IS NULL OR last_seen >= $3) \
+        ORDER BY created",
+        )
+        .await?;
+
+    let result = client
+        .query(
+            &stmt,
+            &[&personnel_nr, &authenticated_after, &last_seen_after],
+        )
+        .await?;
+
+    let sessions = result.into_iter().map(|r| r.into()).collect();
+    Ok(sessions)
+}
+
+pub async fn touch_session(
+    client: &Client,
+    token: &[u8],
+    last_seen: DateTime<Utc>,
+    authenticated: Option<DateTime<Utc>>,
+) -> Result<(), DatabaseError> {
+    let stmt = client
+        .prepare(
+            "UPDATE security.sessions \
+            SET last_seen = $2, authenticated = COALESCE($3, authenticated) \
+            WHERE token = $1",
+        )
+        .await?;
+
+    client
+        .execute(&stmt, &[&token, &last_seen, &authenticated])
+        .await?;
+    Ok(())
+}
+
+/// returns id and personnel nr of removed session
+pub async fn delete_session(
+    client: &Client,
+    token: &[u8],
+) -> Result<Option<(Uuid, Option<i16>)>, DatabaseError> {
+    let stmt = client
+        .prepare("DELETE FROM security.sessions WHERE token = $1 RETURNING id, personnel_nr")
+        .await?;
+
+    let result = client.query_opt(&stmt, &[&token]).await?;
+    Ok(result.map(|r| (r.get(0), r.get(1))))
+}
+
+/// returns id and personnel nr of removed session
+pub async fn delete_session_by_refresh_token(
+    client: &Client,
+    refresh_token: &[u8],
+) -> Result<Option<(Uuid, Option<i16>)>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "DELETE FROM security.sessions \
+            WHERE id = (SELECT session_id FROM security.refresh_tokens WHERE token = $1) \
+            RETURNING id, personnel_nr",
+        )
+        .await?;
+
+    let result = client.query_opt(&stmt, &[&refresh_token]).await?;
+    Ok(result.map(|r| (r.get(0), r.get(1))))
+}
+
+pub async fn delete_session_of_user(
+    client: &Client,
+    personnel_nr: i16,
+    id: Uuid,
+) -> Result<bool, DatabaseError> {
+    let stmt = client
+        .prepare("DELETE FROM security.sessions WHERE personnel_nr = $1 AND id = $2")
+        .await?;
+
+    let count = client.execute(&stmt, &[&personnel_nr, &id]).await?;
+    Ok(count > 0)
+}
+
+/// returns count of removed sessions
+pub async fn delete_other_sessions_of_user(
+    client: &Client,
+    personnel_nr: i16,
+    keep_id: Uuid,
+) -> Result<u64, DatabaseError> {
+    let stmt = client
+        .prepare("DELETE FROM security.sessions WHERE personnel_nr = $1 AND id <> $2")
+        .await?;
+
+    let count = client.execute(&stmt, &[&personnel_nr, &keep_id]).await?;
+    Ok(count)
+}
+
+/// removes all sessions of user, not of service clients
+pub async fn delete_sessions_of_user(
+    client: &Client,
+    personnel_nr: i16,
+) -> Result<u64, DatabaseError> {
+    let stmt = client
+        .prepare("DELETE FROM security.sessions WHERE personnel_nr = $1 AND client_id IS NULL")
+        .await?;
+
+    let count = client.execute(&stmt, &[&personnel_nr]).await?;
+    Ok(count)
+}
+
+/// users with at least one session
+pub async fn find_session_owners(client: &Client) -> Result<Vec<i16>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "SELECT DISTINCT personnel_nr FROM security.sessions WHERE personnel_nr IS NOT NULL",
+        )
+        .await?;
+
+    let rows = client.query(&stmt, &[]).await?;
+    Ok(rows.into_iter().map(|r| r.get(0)).collect())
+}
+
+/// those of `personnel_nrs` who exist and are neither disabled nor dismissed
+pub async fn find_active_users(
+    client: &Client,
+    personnel_nrs: &[i16],
+) -> Result<Vec<i16>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "SELECT personnel_nr FROM security.users \
+            WHERE personnel_nr = ANY($1) AND NOT account_disabled AND date_dismiss IS NULL",
+        )
+        .await?;
+
+    let rows = client.query(&stmt, &[&personnel_nrs]).await?;
+    Ok(rows.into_iter().map(|r| r.get(0)).collect())
+}
+
+/// replace auth token of session; returns false if there is no such session
+pub async fn rekey_session(
+    client: &Client,
+    id: Uuid,
+    token: &[u8],
+    token_issued: DateTime<Utc>,
+) -> Result<bool, DatabaseError> {
+    let stmt = client
+        .prepare("UPDATE security.sessions SET token = $2, token_issued = $3 WHERE id = $1")
+        .await?;
+
+    let count = client.execute(&stmt, &[&id, &token, &token_issued]).await?;
+    Ok(count > 0)
+}
+
+/// returns personnel nr of removed session
+pub async fn delete_session_by_id(client: &Client, id: Uuid) -> Result<Option<i16>, DatabaseError> {
+    let stmt = client
+        .prepare("DELETE FROM security.sessions WHERE id = $1 RETURNING personnel_nr")
+        .await?;
+
+    let result = client.query_opt(&stmt, &[&id]).await?;
+    Ok(result.map(|r| r.get(0)))
+}
+
+pub async fn insert_refresh_token(
+    client: &Client,
+    token: &[u8],
+    session_id: Uuid,
+) -> Result<(), DatabaseError> {
+    let stmt = client
+        .prepare("INSERT INTO security.refresh_tokens (token, session_id) VALUES ($1, $2)")
+        .await?;
+
+    client.execute(&stmt, &[&token, &session_id]).await?;
+    Ok(())
+}
+
+/// mark refresh token as used;
+/// returns false if the token was used before
+pub async fn use_refresh_token(client: &Client, token: &[u8]) -> Result<bool, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "UPDATE security.refresh_tokens SET used = now() \
+            WHERE token = $1 AND used IS NULL",
+        )
+        .await?;
+
+    let count = client.execute(&stmt, &[&token]).await?;
+    Ok(count > 0)
+}
+
+/// session id of refresh token with the oauth client the session was issued to
+pub async fn find_refresh_token_session(
+    client: &Client,
+    token: &[u8],
+) -> Result<Option<(Uuid, Option<String>)>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "SELECT r.session_id, s.issued_to \
+            FROM security.refresh_tokens r \
+            JOIN security.sessions s ON s.id = r.session_id \
+            WHERE r.token = $1",
+        )
+        .await?;
+
+    let result = client.query_opt(&stmt, &[&token]).await?;
+    Ok(result.map(|r| (r.get(0), r.get(1))))
+}
+
+pub async fn delete_expired_sessions(
+    client: &Client,
+    authenticated_before: DateTime<Utc>,
+    last_seen_before: Option<DateTime<Utc>>,
+) -> Result<u64, DatabaseError> {
+    let stmt = client
+        .prepare("DELETE FROM security.sessions WHERE authenticated < $1 OR last_seen < $2")
+        .await?;
+
+    let count = client
+        .execute(&stmt, &[&authenticated_before, &last_seen_before])
+        .await?;
+    Ok(count)
+}
+
+pub async fn find_oauth_client(
+    client: &Client,
+    client_id: &str,
+) -> Result<Option<domain::OAuthClient>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "SELECT client_id, name, salt, secret, disabled, service \
+        FROM security.oauth_clients \
+        WHERE client_id = $1",
+        )
+        .await?;
+
+    let result = client.query_opt(&stmt, &[&client_id]).await?;
+
+    let oauth_client = result.map(|r| r.into());
+    Ok(oauth_client)
+}
+
+pub async fn find_oauth_client_redirect_uris(
+    client: &Client,
+    client_id: &str,
+) -> Result<Vec<String>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "SELECT redirect_uri \
+        FROM security.oauth_client_redirect_uris \
+        WHERE client_id = $1",
+        )
+        .await?;
+
+    let rows = client.query(&stmt, &[&client_id]).await?;
+    Ok(rows.into_iter().map(|r| r.get(0)).collect())
+}
+
+pub async fn insert_authorization_code(
+    client: &Client,
+    code: &domain::AuthorizationCode,
+) -> Result<(), DatabaseError> {
+    let stmt = client
+        .prepare(
+            "INSERT INTO security.oauth_authorization_codes \
+            (code, client_id, personnel_nr, redirect_uri, code_challenge, scope, expires, nonce) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .await?;
+
+    client
+        .execute(
+            &stmt,
+            &[
+                &code.code,
+                &code.client_id,
+                &code.personnel_nr,
+                &code.redirect_uri,
+                &code.code_challenge,
+                &code.scope,
+                &code.expires,
+                &code.nonce,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// marks the code used; `None` when unknown, expired or already used
+pub async fn use_authorization_code(
+    client: &Client,
+    code: Uuid,
+    now: DateTime<Utc>,
+) -> Result<Option<domain::AuthorizationCode>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "UPDATE security.oauth_authorization_codes SET used = now() \
+            WHERE code = $1 AND used IS NULL AND expires > $2 \
+            RETURNING code, client_id, personnel_nr, redirect_uri, code_challenge, scope, expires, \
+            nonce",
+        )
+        .await?;
+
+    let result = client.query_opt(&stmt, &[&code, &now]).await?;
+    Ok(result.map(|r| r.into()))
+}
+
+pub async fn insert_token_revocation(
+    client: &Client,
+    revocation: &domain::TokenRevocation,
+) -> Result<(), DatabaseError> {
+    let stmt = client
+        .prepare(
+            "INSERT INTO security.token_revocations \
+            (session_id, personnel_nr, token_type, revoked_by) \
+            VALUES ($1, $2, $3, $4)",
+        )
+        .await?;
+
+    client
+        .execute(
+            &stmt,
+            &[
+                &revocation.session_id,
+                &revocation.personnel_nr,
+                &revocation.token_type,
+                &revocation.revoked_by,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// stores a new reset code; older unused codes of the user are invalidated
+pub async fn insert_password_reset_code(
+    client: &Client,
+    code: &domain::PasswordResetCode,
+) -> Result<(), DatabaseError> {
+    let stmt = client
+        .prepare(
+            "WITH invalidated AS ( \
+                UPDATE security.password_reset_codes SET used = now() \
+                WHERE personnel_nr = $2 AND used IS NULL \
+            ) \
+            INSERT INTO security.password_reset_codes (code, personnel_nr, expires) \
+            VALUES ($1, $2, $3)",
+        )
+        .await?;
+
+    client
+        .execute(&stmt, &[&code.code, &code.personnel_nr, &code.expires])
+        .await?;
+    Ok(())
+}
+
+/// personnel nr of a valid reset code; the code stays unused
+pub async fn find_password_reset_code(
+    client: &Client,
+    code: &[u8],
+    now: DateTime<Utc>,
+) -> Result<Option<i16>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "SELECT personnel_nr FROM security.password_reset_codes \
+            WHERE code = $1 AND used IS NULL AND expires > $2",
+        )
+        .await?;
+
+    let result = client.query_opt(&stmt, &[&code, &now]).await?;
+    Ok(result.map(|r| r.get(0)))
+}
+
+/// marks the code used; `None` when unknown, expired or already used
+pub async fn use_password_reset_code(
+    client: &Client,
+    code: &[u8],
+    now: DateTime<Utc>,
+) -> Result<Option<i16>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "UPDATE security.password_reset_codes SET used = now() \
+            WHERE code = $1 AND used IS NULL AND expires > $2 \
+            RETURNING personnel_nr",
+        )
+        .await?;
+
+    let result = client.query_opt(&stmt, &[&code, &now]).await?;
+    Ok(result.map(|r| r.get(0)))
+}
+
+/// mail which is useless after `expires` is not sent later and its body is cleared then
+pub async fn insert_outbox_mail(
+    client: &Client,
+    recipient: &str,
+    subject: &str,
+    body: &str,
+    expires: Option<DateTime<Utc>>,
+) -> Result<(), DatabaseError> {
+    let stmt = client
+        .prepare(
+            "INSERT INTO security.mail_outbox (recipient, subject, body, expires) \
+            VALUES ($1, $2, $3, $4)",
+        )
+        .await?;
+
+    client
+        .execute(&stmt, &[&recipient, &subject, &body, &expires])
+        .await?;
+    Ok(())
+}
+
+/// takes up to `limit` unsent mails due for delivery and postpones them until `retry_at`,
+/// so that other server instances don't send them as well
+pub async fn claim_outbox_mail(
+    client: &Client,
+    max_attempts: i32,
+    retry_at: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<domain::OutboxMail>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "UPDATE security.mail_outbox \
+            SET attempts = attempts + 1, next_attempt = $2 \
+            WHERE id IN ( \
+                SELECT id FROM security.mail_outbox \
+                WHERE sent IS NULL AND body IS NOT NULL AND attempts < $1 AND next_attempt <= now() \
+                ORDER BY id LIMIT $3 \
+                FOR UPDATE SKIP LOCKED \
+            ) \
+            RETURNING id, recipient, subject, body, attempts",
+        )
+        .await?;
+
+    let rows = client
+        .query(&stmt, &[&max_attempts, &retry_at, &limit])
+        .await?;
+    Ok(rows.into_iter().map(|r| r.into()).collect())
+}
+
+pub async fn mark_outbox_mail_sent(client: &Client, id: i64) -> Result<(), DatabaseError> {
+    let stmt = client
+        .prepare(
+            "UPDATE security.mail_outbox SET sent = now(), body = NULL, last_error = NULL \
+            WHERE id = $1",
+        )
+        .await?;
+
+    client.execute(&stmt, &[&id]).await?;
+    Ok(())
+}
+
+/// clears the bodies of unsent mail past its expiry; returns count of cleared mails
+pub async fn clear_expired_outbox_mail(client: &Client) -> Result<u64, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "UPDATE security.mail_outbox SET body = NULL \
+            WHERE expires <= now() AND body IS NOT NULL",
+        )
+        .await?;
+
+    let count = client.execute(&stmt, &[]).await?;
+    Ok(count)
+}
+
+pub async fn mark_outbox_mail_failed(
+    client: &Client,
+    id: i64,
+    error: &str,
+) -> Result<(), DatabaseError> {
+    let stmt = client
+        .prepare("UPDATE security.mail_outbox SET last_error = $2 WHERE id = $1")
+        .await?;
+
+    client.execute(&stmt, &[&id, &error]).await?;
+    Ok(())
+}
+
+/// counts a login with an expired password; `None` when all `max` grace logins are used
+pub async fn use_grace_login(
+    client: &Client,
+    personnel_nr: i16,
+    max: i32,
+) -> Result<Option<i32>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "UPDATE security.users SET grace_logins_used = grace_logins_used + 1 \
+            WHERE personnel_nr = $1 AND grace_logins_used < $2 \
+            RETURNING grace_logins_used",
+        )
+        .await?;
+
+    let result = client.query_opt(&stmt, &[&personnel_nr, &max]).await?;
+    Ok(result.map(|r| r.get(0)))
+}
+
+/// end of the lockout of an account, while it lasts
+pub async fn find_login_lockout(
+    client: &Client,
+    personnel_nr: i16,
+) -> Result<Option<DateTime<Utc>>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "SELECT locked_until FROM security.login_failures \
+            WHERE personnel_nr = $1 AND locked_until > now()",
+        )
+        .await?;
+
+    let result = client.query_opt(&stmt, &[&personnel_nr]).await?;
+    Ok(result.map(|r| r.get(0)))
+}
+
+/// counts a failed login; returns the consecutive failed logins of the account
+pub async fn record_failed_login(client: &Client, personnel_nr: i16) -> Result<i32, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "INSERT INTO security.login_failures (personnel_nr, failures, last_failure) \
+            VALUES ($1, 1, now()) \
+            ON CONFLICT (personnel_nr) DO UPDATE \
+            SET failures = security.login_failures.failures + 1, last_failure = now() \
+            RETURNING failures",
+        )
+        .await?;
+
+    let row = client.query_one(&stmt, &[&personnel_nr]).await?;
+    Ok(row.get(0))
+}
+
+pub async fn lock_account(
+    client: &Client,
+    personnel_nr: i16,
+    locked_until: DateTime<Utc>,
+) -> Result<(), DatabaseError> {
+    let stmt = client
+        .prepare("UPDATE security.login_failures SET locked_until = $2 WHERE personnel_nr = $1")
+        .await?;
+
+    client
+        .execute(&stmt, &[&personnel_nr, &locked_until])
+        .await?;
+    Ok(())
+}
+
+/// on successful login or unlock by an administrator;
+/// returns false if the account had no failed logins
+pub async fn clear_failed_logins(
+    client: &Client,
+    personnel_nr: i16,
+) -> Result<bool, DatabaseError> {
+    let stmt = client
+        .prepare("DELETE FROM security.login_failures WHERE personnel_nr = $1")
+        .await?;
+
+    let count = client.execute(&stmt, &[&personnel_nr]).await?;
+    Ok(count == 1)
+}
+
+pub async fn list_users(client: &Client) -> Result<Vec<domain::UserAccount>, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "SELECT personnel_nr, username, email, telefon, account_disabled, date_dismiss, \
+            password_expiration_date \
+            FROM security.users \
+            ORDER BY personnel_nr",
+        )
+        .await?;
+
+    let rows = client.query(&stmt, &[]).await?;
+    Ok(rows.into_iter().map(|r| r.into()).collect())
+}
+
+/// returns false if a user with the same personnel nr already exists
+pub async fn insert_user(client: &Client, user: &domain::User) -> Result<bool, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "INSERT INTO security.users \
+            (personnel_nr, salt, password, password_iterations, password_expiration_date, \
+            username, account_disabled, date_dismiss, telefon, email) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) \
+            ON CONFLICT (personnel_nr) DO NOTHING",
+        )
+        .await?;
+
+    let count = client
+        .execute(
+            &stmt,
+            &[
+                &user.personnel_nr,
+                &user.salt,
+                &user.password,
+                &user.password_iterations,
+                &user.password_expiration_date,
+                &user.username,
+                &user.account_disabled,
+                &user.date_dismiss,
+                &user.telefon,
+                &user.email,
+            ],
+        )
+        .await?;
+    Ok(count == 1)
+}
+
+/// returns false if there is no such user
+pub async fn set_account_disabled(
+    client: &Client,
+    personnel_nr: i16,
+    disabled: bool,
+) -> Result<bool, DatabaseError> {
+    let stmt = client
+        .prepare("UPDATE security.users SET account_disabled = $2 WHERE personnel_nr = $1")
+        .await?;
+
+    let count = client.execute(&stmt, &[&personnel_nr, &disabled]).await?;
+    Ok(count == 1)
+}
+
+/// returns false if there is no such user
+pub async fn set_password_expiration(
+    client: &Client,
+    personnel_nr: i16,
+    expiration: NaiveDate,
+) -> Result<bool, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "UPDATE security.users SET password_expiration_date = $2, grace_logins_used = 0 \
+            WHERE personnel_nr = $1",
+        )
+        .await?;
+
+    let count = client.execute(&stmt, &[&personnel_nr, &expiration]).await?;
+    Ok(count == 1)
+}
+
+pub async fn find_role_id(client: &Client, role_name: &str) -> Result<Option<i16>, DatabaseError> {
+    let stmt = client
+        .prepare("SELECT role_id FROM security.roles WHERE role_name = $1")
+        .await?;
+
+    let result = client.query_opt(&stmt, &[&role_name]).await?;
+    Ok(result.map(|r| r.get(0)))
+}
+
+/// returns false if the user already has the role
+pub async fn insert_user_role(
+    client: &Client,
+    personnel_nr: i16,
+    role_id: i16,
+) -> Result<bool, DatabaseError> {
+    let stmt = client
+        .prepare(
+            "INSERT INTO security.user_roles (personnel_nr, role_id) VALUES ($1, $2) \
+            ON CONFLICT DO NOTHING",
+        )
+        .await?;
+
+    let count = client.execute(&stmt, &[&personnel_nr, &role_id]).await?;
+    Ok(count == 1)
+}
+
+/// returns false if the user doesn't have the role
+pub async fn delete_user_role(
+    client: &Client,
+    personnel_nr: i16,
+    role_id: i16,
+) -> Result<bool, DatabaseError> {
+    let stmt = client
+        .prepare("DELETE FROM security.user_roles WHERE personnel_nr = $1 AND role_id = $2")
+        .await?;
+
+    let count = client.execute(&stmt, &[&personnel_nr, &role_id]).await?;
+    Ok(count == 1)
+}
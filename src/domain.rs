@@ -8,6 +8,9 @@ pub struct User {
     pub salt: String,
     #[serde(skip_serializing)]
     pub password: String,
+    /// PBKDF2 iterations of a legacy `password`; 0 for PHC strings
+    #[serde(skip_serializing)]
+    pub password_iterations: i32,
     #[serde(skip_serializing)]
     pub password_expiration_date: chrono::NaiveDate,
     pub username: String,
@@ -31,6 +34,7 @@ impl From<Row> for User {
             date_dismiss: row.get(6),
             telefon: row.get(7),
             email: row.get(8),
+            password_iterations: row.get(9),
         }
     }
 }
@@ -41,6 +45,15 @@ pub struct UserRole {
     pub role_name: String,
 }
 
+/// new credentials of a user
+pub struct PasswordChange {
+    pub personnel_nr: i16,
+    pub salt: String,
+    pub password: String,
+    pub password_iterations: i32,
+    pub password_expiration_date: chrono::NaiveDate,
+}
+
 impl From<Row> for UserRole {
     fn from(row: Row) -> Self {
         Self {
@@ -66,3 +79,189 @@ impl From<Row> for UserResource {
         }
     }
 }
+
+pub struct Session {
+    pub id: uuid::Uuid,
+    /// SHA-256 digest of the auth token
+    pub token: Vec<u8>,
+    /// none for sessions of service clients
+    pub personnel_nr: Option<i16>,
+    pub created: chrono::DateTime<chrono::Utc>,
+    pub authenticated: chrono::DateTime<chrono::Utc>,
+    pub last_seen: chrono::DateTime<chrono::Utc>,
+    pub user_agent: Option<String>,
+    pub client_ip: Option<String>,
+    pub token_issued: chrono::DateTime<chrono::Utc>,
+    /// service client of the session, if any
+    pub client_id: Option<String>,
+    /// session of a grace login, which may only change the password
+    pub restricted: bool,
+    /// oauth client the tokens were issued to by the authorization code grant
+    pub issued_to: Option<String>,
+}
+
+impl From<Row> for Session {
+    fn from(row: Row) -> Self {
+        Self {
+            id: row.get(0),
+            token: row.get(1),
+            personnel_nr: row.get(2),
+            created: row.get(3),
+            authenticated: row.get(4),
+            last_seen: row.get(5),
+            user_agent: row.get(6),
+            client_ip: row.get(7),
+            token_issued: row.get(8),
+            client_id: row.get(9),
+            restricted: row.get(10),
+            issued_to: row.get(11),
+        }
+    }
+}
+
+pub struct OAuthClient {
+    pub client_id: String,
+    pub name: String,
+    pub salt: String,
+    pub secret: String,
+    pub disabled: bool,
+    /// may use the client credentials grant
+    pub service: bool,
+}
+
+impl From<Row> for OAuthClient {
+    fn from(row: Row) -> Self {
+        Self {
+            client_id: row.get(0),
+            name: row.get(1),
+            salt: row.get(2),
+            secret: row.get(3),
+            disabled: row.get(4),
+            service: row.get(5),
+        }
+    }
+}
+
+pub struct TokenRevocation {
+    pub session_id: uuid::Uuid,
+    /// none for sessions of service clients
+    pub personnel_nr: Option<i16>,
+    pub token_type: &'static str,
+    /// client id, or personnel nr of administrator
+    pub revoked_by: String,
+}
+
+/// issued by `/authorize`, exchanged for tokens at `/token`
+pub struct AuthorizationCode {
+    pub code: uuid::Uuid,
+    pub client_id: String,
+    pub personnel_nr: i16,
+    pub redirect_uri: String,
+    pub code_challenge: String,
+    pub scope: Option<String>,
+    pub expires: chrono::DateTime<chrono::Utc>,
+    /// OpenID Connect nonce
+    pub nonce: Option<String>,
+}
+
+impl From<Row> for AuthorizationCode {
+    fn from(row: Row) -> Self {
+        Self {
+            code: row.get(0),
+            client_id: row.get(1),
+            personnel_nr: row.get(2),
+            redirect_uri: row.get(3),
+            code_challenge: row.get(4),
+            scope: row.get(5),
+            expires: row.get(6),
+            nonce: row.get(7),
+        }
+    }
+}
+
+/// earlier password of a user
+pub struct PasswordHistoryEntry {
+    pub salt: String,
+    pub password: String,
+    pub password_iterations: i32,
+}
+
+impl From<Row> for PasswordHistoryEntry {
+    fn from(row: Row) -> Self {
+        Self {
+            salt: row.get(0),
+            password: row.get(1),
+            password_iterations: row.get(2),
+        }
+    }
+}
+
+/// one-time code of the forgot-password flow
+pub struct PasswordResetCode {
+    /// SHA-256 digest of the code
+    pub code: Vec<u8>,
+    pub personnel_nr: i16,
+    pub expires: chrono::DateTime<chrono::Utc>,
+}
+
+/// mail waiting in the outbox
+pub struct OutboxMail {
+    pub id: i64,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    /// delivery attempts, including the current one
+    pub attempts: i32,
+}
+
+impl From<Row> for OutboxMail {
+    fn from(row: Row) -> Self {
+        Self {
+            id: row.get(0),
+            recipient: row.get(1),
+            subject: row.get(2),
+            body: row.get(3),
+            attempts: row.get(4),
+        }
+    }
+}
+
+/// user as shown to administrators
+#[derive(Serialize)]
+pub struct UserAccount {
+    pub personnel_nr: i16,
+    pub username: String,
+    pub email: Option<String>,
+    pub telefon: Option<String>,
+    pub account_disabled: bool,
+    pub date_dismiss: Option<chrono::NaiveDate>,
+    pub password_expiration_date: chrono::NaiveDate,
+}
+
+impl From<Row> for UserAccount {
+    fn from(row: Row) -> Self {
+        Self {
+            personnel_nr: row.get(0),
+            username: row.get(1),
+            email: row.get(2),
+            telefon: row.get(3),
+            account_disabled: row.get(4),
+            date_dismiss: row.get(5),
+            password_expiration_date: row.get(6),
+        }
+    }
+}
+
+impl From<User> for UserAccount {
+    fn from(user: User) -> Self {
+        Self {
+            personnel_nr: user.personnel_nr,
+            username: user.username,
+            email: user.email,
+            telefon: user.telefon,
+            account_disabled: user.account_disabled,
+            date_dismiss: user.date_dismiss,
+            password_expiration_date: user.password_expiration_date,
+        }
+    }
+}
@@ -1,10 +1,15 @@
-use actix_web::http::header::ContentType;
+use actix_web::http::header::{self, ContentType};
 use actix_web::http::StatusCode;
 use actix_web::{error, HttpResponse};
+use chrono::{DateTime, Utc};
 use deadpool_postgres::PoolError;
 use derive_more::{Display, Error};
+use serde::Serialize;
 use tokio_postgres::error::Error as PGError;
 
+use crate::identity::{PolicyViolation, Quota};
+use crate::locale::Language;
+
 #[derive(Display, Debug, Error)]
 pub enum DatabaseError {
     PGError(PGError),
@@ -30,3 +35,234 @@ impl error::ResponseError for DatabaseError {
             .body(self.to_string())
     }
 }
+
+/// Failure to build or deliver a mail
+#[derive(Display, Debug, Error)]
+pub enum MailError {
+    Io(std::io::Error),
+    Address(lettre::address::AddressError),
+    Message(lettre::error::Error),
+    Smtp(lettre::transport::smtp::Error),
+    File(lettre::transport::file::Error),
+}
+
+impl std::convert::From<std::io::Error> for MailError {
+    fn from(error: std::io::Error) -> Self {
+        MailError::Io(error)
+    }
+}
+
+impl std::convert::From<lettre::address::AddressError> for MailError {
+    fn from(error: lettre::address::AddressError) -> Self {
+        MailError::Address(error)
+    }
+}
+
+impl std::convert::From<lettre::error::Error> for MailError {
+    fn from(error: lettre::error::Error) -> Self {
+        MailError::Message(error)
+    }
+}
+
+impl std::convert::From<lettre::transport::smtp::Error> for MailError {
+    fn from(error: lettre::transport::smtp::Error) -> Self {
+        MailError::Smtp(error)
+    }
+}
+
+impl std::convert::From<lettre::transport::file::Error> for MailError {
+    fn from(error: lettre::transport::file::Error) -> Self {
+        MailError::File(error)
+    }
+}
+
+/// New password rejected by the password policy; messages are in the language of the request
+#[derive(Debug)]
+pub struct PasswordPolicyError {
+    pub violations: Vec<PolicyViolation>,
+    pub language: Language,
+}
+
+impl std::fmt::Display for PasswordPolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let messages: Vec<_> = self
+            .violations
+            .iter()
+            .map(|violation| violation.message(self.language))
+            .collect();
+        write!(f, "{}", messages.join("; "))
+    }
+}
+
+#[derive(Serialize)]
+struct PolicyViolationBody {
+    code: &'static str,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct PasswordPolicyErrorBody {
+    error: &'static str,
+    message: String,
+    violations: Vec<PolicyViolationBody>,
+}
+
+impl PasswordPolicyError {
+    fn body(&self) -> PasswordPolicyErrorBody {
+        let violations = self
+            .violations
+            .iter()
+            .map(|violation| PolicyViolationBody {
+                code: violation.code(),
+                message: violation.message(self.language),
+            })
+            .collect();
+        PasswordPolicyErrorBody {
+            error: "password_policy",
+            message: self.to_string(),
+            violations,
+        }
+    }
+}
+
+/// same shape as the error response, for callers outside of actix
+impl Serialize for PasswordPolicyError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.body().serialize(serializer)
+    }
+}
+
+impl error::ResponseError for PasswordPolicyError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self.body())
+    }
+}
+
+/// Login to an account locked after too many failed logins
+#[derive(Debug)]
+pub struct AccountLockedError {
+    pub locked_until: DateTime<Utc>,
+}
+
+impl AccountLockedError {
+    /// whole seconds until the lockout ends, at least 1
+    fn retry_after_secs(&self) -> i64 {
+        let remaining = self.locked_until - Utc::now();
+        (remaining.num_milliseconds() + 999).div_euclid(1000).max(1)
+    }
+}
+
+impl std::fmt::Display for AccountLockedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Cont blocat după prea multe încercări eșuate; Încercați din nou peste {} secunde",
+            self.retry_after_secs()
+        )
+    }
+}
+
+impl error::ResponseError for AccountLockedError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .insert_header((header::RETRY_AFTER, self.retry_after_secs()))
+            .insert_header(ContentType::html())
+            .body(self.to_string())
+    }
+}
+
+/// Request over a rate limit of the credential endpoints
+#[derive(Debug)]
+pub struct RateLimitedError {
+    pub quota: Quota,
+}
+
+impl std::fmt::Display for RateLimitedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Prea multe cereri; Încercați din nou peste {} secunde",
+            self.quota.retry_after_secs.unwrap_or_default()
+        )
+    }
+}
+
+impl error::ResponseError for RateLimitedError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code())
+            .insert_header((
+                header::RETRY_AFTER,
+                self.quota.retry_after_secs.unwrap_or_default(),
+            ))
+            .insert_header(ContentType::html())
+            .body(self.to_string());
+        self.quota.insert_headers(response.headers_mut());
+        response
+    }
+}
+
+/// OAuth2 error response, RFC 6749 section 5.2
+#[derive(Display, Debug)]
+pub enum OAuthError {
+    #[display(fmt = "invalid_request: {}", _0)]
+    InvalidRequest(&'static str),
+    #[display(fmt = "invalid_client")]
+    InvalidClient,
+    #[display(fmt = "invalid_grant: {}", _0)]
+    InvalidGrant(&'static str),
+    #[display(fmt = "unauthorized_client")]
+    UnauthorizedClient,
+    #[display(fmt = "unsupported_grant_type")]
+    UnsupportedGrantType,
+    #[display(fmt = "unsupported_token_type")]
+    UnsupportedTokenType,
+}
+
+#[derive(Serialize)]
+struct OAuthErrorBody {
+    error: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_description: Option<&'static str>,
+}
+
+impl OAuthError {
+    fn body(&self) -> OAuthErrorBody {
+        let (error, error_description) = match *self {
+            OAuthError::InvalidRequest(description) => ("invalid_request", Some(description)),
+            OAuthError::InvalidClient => ("invalid_client", None),
+            OAuthError::InvalidGrant(description) => ("invalid_grant", Some(description)),
+            OAuthError::UnauthorizedClient => ("unauthorized_client", None),
+            OAuthError::UnsupportedGrantType => ("unsupported_grant_type", None),
+            OAuthError::UnsupportedTokenType => ("unsupported_token_type", None),
+        };
+        OAuthErrorBody {
+            error,
+            error_description,
+        }
+    }
+}
+
+impl error::ResponseError for OAuthError {
+    fn status_code(&self) -> StatusCode {
+        match *self {
+            OAuthError::InvalidClient => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let OAuthError::InvalidClient = self {
+            response.insert_header((header::WWW_AUTHENTICATE, "Basic"));
+        }
+        response.json(self.body())
+    }
+}
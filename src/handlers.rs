@@ -1,14 +1,28 @@
-use crate::database::{count_of_roles, find_user_by_name, load_user_resources, load_user_roles};
+use crate::database::{
+    clear_failed_logins, count_of_roles, find_login_lockout, find_password_reset_code,
+    find_user_by_name, insert_outbox_mail, insert_password_reset_code, load_client_resources,
+    load_client_roles, load_password_history, load_user_resources, load_user_roles, lock_account,
+    record_failed_login, rehash_user_password, update_user_password, use_grace_login,
+    use_password_reset_code,
+};
 use crate::dto::TRUE_RESPONSE;
-use crate::errors::DatabaseError;
-use crate::identity::{AuthTokenContext, AuthenticattionInfoContext, Authorization, Identity};
+use crate::errors::{AccountLockedError, DatabaseError};
+use crate::identity::{
+    parse_reset_code, AuthTokenContext, AuthenticattionInfoContext, Authorization, Identity,
+    SessionDevice,
+};
+use crate::locale::Language;
+use crate::mail::{password_reset_body, PASSWORD_RESET_SUBJECT};
+use crate::setup::PasswordConfig;
 
-use actix_web::{get, post, web, HttpResponse, Responder, Result};
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder, Result};
+use chrono::Utc;
 use deadpool_postgres::Pool;
 use futures_util::try_join;
 use serde::{Deserialize, Serialize};
 
 use std::sync::Arc;
+use uuid::Uuid;
 
 #[get("/")]
 pub async fn hello(db_pool: web::Data<Pool>) -> Result<HttpResponse> {
@@ -24,10 +38,16 @@ use actix_web::dev::HttpServiceFactory;
 
 pub fn auth_scope() -> impl HttpServiceFactory {
     web::scope("/auth")
-        .wrap(Authorization::enable())
+        .wrap(Authorization::enable().allow_restricted("/auth/password"))
         .service(auth_info)
         .service(auth_permissions)
+        .service(auth_refresh_permissions)
+        .service(auth_change_password)
         .service(auth_test)
+        .service(auth_sessions)
+        .service(auth_logout_other_sessions)
+        .service(auth_revoke_session)
+        .service(auth_unlock_account)
 }
 
 #[derive(Deserialize)]
@@ -40,8 +60,10 @@ use std::str::FromStr;
 
 #[post("/login")]
 pub async fn login(
+    req: HttpRequest,
     db_pool: web::Data<Pool>,
     identity: web::Data<Identity>,
+    config: web::Data<PasswordConfig>,
     credentials: web::Json<UsernamePasswordCredentials>,
 ) -> Result<impl Responder> {
     let client = db_pool.get().await.map_err(DatabaseError::PoolError)?;
@@ -61,18 +83,252 @@ pub async fn login(
         &user.personnel_nr
     );
 
-    identity.verify_authentication(&user, &credentials.password)?;
+    verify_password_with_lockout(&client, &identity, &user, &credentials.password).await?;
+    identity.verify_account(&user)?;
+    let restricted = if identity.password_expired(&user) {
+        let used = use_grace_login(&client, personnel_nr, config.grace_logins)
+            .await?
+            .ok_or(actix_web::error::ErrorUnauthorized(
+                "Parola este învechita; Schimbați parola",
+            ))?;
+        log::info!(
+            "grace login {} of {} for user {} with expired password",
+            used,
+            config.grace_logins,
+            personnel_nr
+        );
+        true
+    } else {
+        false
+    };
+    upgrade_password_hash(&client, &identity, &user, &credentials.password).await;
 
     let roles = load_user_roles(&client, personnel_nr);
     let resources = load_user_resources(&client, personnel_nr);
 
     let (roles, resources) = try_join!(roles, resources)?;
 
-    let response = identity.authenticate(user, roles, resources)?;
+    let device = SessionDevice::of(&req);
+    let response = identity
+        .authenticate(user, roles, resources, device, restricted, None)
+        .await?;
 
     Ok(web::Json(response))
 }
 
+/// password check of the login endpoints: locked accounts are rejected before hashing,
+/// failed logins are counted and lock the account at the threshold, a right password
+/// clears the count
+pub async fn verify_password_with_lockout(
+    client: &deadpool_postgres::Client,
+    identity: &Identity,
+    user: &crate::domain::User,
+    password: &str,
+) -> Result<()> {
+    let personnel_nr = user.personnel_nr;
+    if let Some(locked_until) = find_login_lockout(client, personnel_nr).await? {
+        return Err(AccountLockedError { locked_until }.into());
+    }
+
+    if let Err(err) = identity.verify_password(user, password) {
+        let failures = record_failed_login(client, personnel_nr).await?;
+        if let Some(locked_until) = identity.lockout_after(failures) {
+            lock_account(client, personnel_nr, locked_until).await?;
+            log::warn!(
+                "user {} locked until {} after {} failed logins",
+                personnel_nr,
+                locked_until,
+                failures
+            );
+        }
+        return Err(err);
+    }
+    clear_failed_logins(client, personnel_nr).await?;
+    Ok(())
+}
+
+/// stores the hash of a verified password with the current cost, when needed;
+/// failures only delay the upgrade to a later login
+pub async fn upgrade_password_hash(
+    client: &deadpool_postgres::Client,
+    identity: &Identity,
+    user: &crate::domain::User,
+    password: &str,
+) {
+    let change = match identity.rehash_password(user, password) {
+        Some(change) => change,
+        None => return,
+    };
+    match rehash_user_password(client, &change, &user.password).await {
+        Ok(true) => log::info!("password hash of user {} upgraded", user.personnel_nr),
+        Ok(false) => {}
+        Err(err) => log::warn!(
+            "failed to upgrade password hash of user {}: {}",
+            user.personnel_nr,
+            err
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExpiredPasswordChangeRequest {
+    username: String,
+    current_password: String,
+    new_password: String,
+}
+
+/// change-only flow for users whose password has expired;
+/// afterwards they log in with the new password
+#[post("/password/expired")]
+pub async fn change_expired_password(
+    db_pool: web::Data<Pool>,
+    identity: web::Data<Identity>,
+    config: web::Data<PasswordConfig>,
+    language: Language,
+    request: web::Json<ExpiredPasswordChangeRequest>,
+) -> Result<impl Responder> {
+    let client = db_pool.get().await.map_err(DatabaseError::PoolError)?;
+
+    let personnel_nr: i16 = FromStr::from_str(&request.username)
+        .map_err(|_| actix_web::error::ErrorBadRequest("username must be personnel nr: number"))?;
+
+    let user = find_user_by_name(&client, personnel_nr).await?.ok_or(
+        actix_web::error::ErrorUnauthorized("Utilizatorul cu acest nume nu este autentificat"),
+    )?;
+
+    verify_password_with_lockout(&client, &identity, &user, &request.current_password).await?;
+    let history = load_password_history(&client, personnel_nr, config.history_depth as i64).await?;
+    let change =
+        identity.change_expired_password(&user, &request.new_password, &history, language)?;
+    update_user_password(&client, &change, config.history_depth as i64).await?;
+
+    log::info!("user {} changed expired password", user.personnel_nr);
+
+    Ok(web::Json(TRUE_RESPONSE))
+}
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordRequest {
+    username: String,
+}
+
+/// mails a one-time reset code to the user;
+/// answers the same for unknown users, so that accounts can't be discovered
+#[post("/password/forgot")]
+pub async fn forgot_password(
+    db_pool: web::Data<Pool>,
+    identity: web::Data<Identity>,
+    config: web::Data<PasswordConfig>,
+    request: web::Json<ForgotPasswordRequest>,
+) -> Result<impl Responder> {
+    let client = db_pool.get().await.map_err(DatabaseError::PoolError)?;
+
+    let personnel_nr: i16 = FromStr::from_str(&request.username)
+        .map_err(|_| actix_web::error::ErrorBadRequest("username must be personnel nr: number"))?;
+
+    let user = match find_user_by_name(&client, personnel_nr).await? {
+        Some(user) => user,
+        None => {
+            log::info!("password reset requested for unknown user {}", personnel_nr);
+            return Ok(web::Json(TRUE_RESPONSE));
+        }
+    };
+    let email = match user.email.as_deref().filter(|it| !it.is_empty()) {
+        Some(email) => email,
+        None => {
+            log::info!(
+                "password reset requested for user {} without email",
+                personnel_nr
+            );
+            return Ok(web::Json(TRUE_RESPONSE));
+        }
+    };
+    let (code, reset_code) = match identity.password_reset_code(&user) {
+        Ok(it) => it,
+        Err(err) => {
+            log::info!("password reset refused for user {}: {}", personnel_nr, err);
+            return Ok(web::Json(TRUE_RESPONSE));
+        }
+    };
+
+    insert_password_reset_code(&client, &reset_code).await?;
+    let body = password_reset_body(&code, &config.reset_link, reset_code.expires);
+    insert_outbox_mail(
+        &client,
+        email,
+        PASSWORD_RESET_SUBJECT,
+        &body,
+        Some(reset_code.expires),
+    )
+    .await?;
+
+    log::info!("password reset code sent to user {}", personnel_nr);
+
+    Ok(web::Json(TRUE_RESPONSE))
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    code: String,
+    new_password: String,
+}
+
+/// sets a new password with a code from the forgot-password mail;
+/// all sessions of the user are closed
+#[post("/password/reset")]
+pub async fn reset_password(
+    db_pool: web::Data<Pool>,
+    identity: web::Data<Identity>,
+    config: web::Data<PasswordConfig>,
+    language: Language,
+    request: web::Json<PasswordResetRequest>,
+) -> Result<impl Responder> {
+    let client = db_pool.get().await.map_err(DatabaseError::PoolError)?;
+
+    let code = parse_reset_code(&request.code)?;
+    let now = Utc::now();
+    let invalid_code =
+        || actix_web::error::ErrorBadRequest("Codul de resetare este invalid sau expirat");
+
+    let personnel_nr = find_password_reset_code(&client, code.as_ref(), now)
+        .await?
+        .ok_or_else(invalid_code)?;
+    let user = find_user_by_name(&client, personnel_nr)
+        .await?
+        .ok_or_else(invalid_code)?;
+
+    let history = load_password_history(&client, personnel_nr, config.history_depth as i64).await?;
+    let change = identity.reset_password(&user, &request.new_password, &history, language)?;
+    // the code is spent only by a successful reset
+    use_password_reset_code(&client, code.as_ref(), now)
+        .await?
+        .ok_or_else(invalid_code)?;
+    update_user_password(&client, &change, config.history_depth as i64).await?;
+    let closed = identity.revoke_sessions_of_user(personnel_nr).await?;
+
+    log::info!(
+        "user {} reset password, closed {} sessions",
+        personnel_nr,
+        closed
+    );
+
+    Ok(web::Json(TRUE_RESPONSE))
+}
+
+#[derive(Deserialize)]
+pub struct RefreshTokenRequest {
+    refresh_token: String,
+}
+
+#[post("/token/refresh")]
+pub async fn refresh_token(
+    identity: web::Data<Identity>,
+    request: web::Json<RefreshTokenRequest>,
+) -> Result<impl Responder> {
+    let response = identity.refresh(&request.refresh_token, None).await?;
+    Ok(web::Json(response))
+}
+
 #[get("/info")]
 pub async fn auth_info(
     auth_context: Option<web::ReqData<AuthenticattionInfoContext>>,
@@ -104,13 +360,111 @@ pub async fn auth_permissions(
     let auth_user = auth_context.auth_info.clone();
     let info = AuthenticationInfo {
         personnel_nr: auth_user.user.personnel_nr,
-        roles: auth_user.roles.clone(),
-        resources: auth_user.resources.clone(),
+        roles: auth_user.roles(),
+        resources: auth_user.resources(),
+    };
+
+    Ok(web::Json(info))
+}
+
+/// reload roles and resources into the live sessions of the user
+#[post("/refresh-permissions")]
+pub async fn auth_refresh_permissions(
+    db_pool: web::Data<Pool>,
+    identity: web::Data<Identity>,
+    auth_context: Option<web::ReqData<AuthenticattionInfoContext>>,
+) -> Result<impl Responder> {
+    let auth_context = auth_context.ok_or(actix_web::error::ErrorInternalServerError(
+        "Authentication info context not found in application",
+    ))?;
+
+    let client = db_pool.get().await.map_err(DatabaseError::PoolError)?;
+
+    let auth_user = auth_context.auth_info.clone();
+    let (roles, resources) = match &auth_user.client_id {
+        Some(client_id) => {
+            let roles = load_client_roles(&client, client_id);
+            let resources = load_client_resources(&client, client_id);
+            try_join!(roles, resources)?
+        }
+        None => {
+            let personnel_nr = auth_user.user.personnel_nr;
+            let roles = load_user_roles(&client, personnel_nr);
+            let resources = load_user_resources(&client, personnel_nr);
+            try_join!(roles, resources)?
+        }
+    };
+
+    identity
+        .refresh_permissions(&auth_user, roles, resources)
+        .await?;
+
+    let info = AuthenticationInfo {
+        personnel_nr: auth_user.user.personnel_nr,
+        roles: auth_user.roles(),
+        resources: auth_user.resources(),
     };
 
     Ok(web::Json(info))
 }
 
+#[derive(Deserialize)]
+pub struct PasswordChangeRequest {
+    current_password: String,
+    new_password: String,
+}
+
+/// change own password; other sessions of the user are closed
+#[post("/password")]
+pub async fn auth_change_password(
+    db_pool: web::Data<Pool>,
+    identity: web::Data<Identity>,
+    auth_context: Option<web::ReqData<AuthenticattionInfoContext>>,
+    config: web::Data<PasswordConfig>,
+    language: Language,
+    request: web::Json<PasswordChangeRequest>,
+) -> Result<impl Responder> {
+    let auth_context = auth_context.ok_or(actix_web::error::ErrorInternalServerError(
+        "Authentication info context not found in application",
+    ))?;
+
+    let auth_user = &auth_context.auth_info;
+    if auth_user.is_service() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Service clients have no password",
+        ));
+    }
+
+    let client = db_pool.get().await.map_err(DatabaseError::PoolError)?;
+
+    let user = find_user_by_name(&client, auth_user.user.personnel_nr)
+        .await?
+        .ok_or(actix_web::error::ErrorUnauthorized(
+            "Utilizatorul cu acest nume nu este autentificat",
+        ))?;
+
+    verify_password_with_lockout(&client, &identity, &user, &request.current_password).await?;
+
+    let history =
+        load_password_history(&client, user.personnel_nr, config.history_depth as i64).await?;
+    let change = identity.change_password(&user, &request.new_password, &history, language)?;
+    update_user_password(&client, &change, config.history_depth as i64).await?;
+
+    // a restricted session has served its purpose; the user logs in again
+    let closed = if auth_user.restricted {
+        identity.revoke_sessions_of_user(user.personnel_nr).await?
+    } else {
+        identity.revoke_other_sessions(auth_user).await?
+    };
+    log::info!(
+        "user {} changed password; closed {} sessions",
+        user.personnel_nr,
+        closed
+    );
+
+    Ok(web::Json(TRUE_RESPONSE))
+}
+
 #[get("/test")]
 pub async fn auth_test(
     auth_context: Option<web::ReqData<AuthenticattionInfoContext>>,
@@ -122,15 +476,109 @@ pub async fn auth_test(
     Ok(web::Json(TRUE_RESPONSE))
 }
 
+#[get("/sessions")]
+pub async fn auth_sessions(
+    identity: web::Data<Identity>,
+    auth_context: Option<web::ReqData<AuthenticattionInfoContext>>,
+) -> Result<impl Responder> {
+    let auth_context = auth_context.ok_or(actix_web::error::ErrorInternalServerError(
+        "Authentication info context not found in application",
+    ))?;
+
+    let sessions = identity.sessions_of(&auth_context.auth_info).await?;
+
+    Ok(web::Json(sessions))
+}
+
+#[delete("/sessions/{id}")]
+pub async fn auth_revoke_session(
+    identity: web::Data<Identity>,
+    auth_context: Option<web::ReqData<AuthenticattionInfoContext>>,
+    id: web::Path<Uuid>,
+) -> Result<impl Responder> {
+    let auth_context = auth_context.ok_or(actix_web::error::ErrorInternalServerError(
+        "Authentication info context not found in application",
+    ))?;
+
+    let revoked = identity
+        .revoke_session(&auth_context.auth_info, id.into_inner())
+        .await?;
+    if !revoked {
+        return Err(actix_web::error::ErrorNotFound("Session not found"));
+    }
+
+    Ok(web::Json(TRUE_RESPONSE))
+}
+
+/// ends the lockout of an account after failed logins; administrators only
+#[delete("/users/{personnel_nr}/lockout")]
+pub async fn auth_unlock_account(
+    db_pool: web::Data<Pool>,
+    identity: web::Data<Identity>,
+    auth_context: Option<web::ReqData<AuthenticattionInfoContext>>,
+    personnel_nr: web::Path<i16>,
+) -> Result<impl Responder> {
+    let auth_context = auth_context.ok_or(actix_web::error::ErrorInternalServerError(
+        "Authentication info context not found in application",
+    ))?;
+    if !identity.is_admin(&auth_context.auth_info) {
+        return Err(actix_web::error::ErrorForbidden(
+            "Only administrators may unlock accounts",
+        ));
+    }
+
+    let client = db_pool.get().await.map_err(DatabaseError::PoolError)?;
+    let personnel_nr = personnel_nr.into_inner();
+    if !clear_failed_logins(&client, personnel_nr).await? {
+        return Err(actix_web::error::ErrorNotFound(
+            "No failed logins for this account",
+        ));
+    }
+    log::info!(
+        "user {} unlocked by {}",
+        personnel_nr,
+        auth_context.auth_info.subject()
+    );
+
+    Ok(web::Json(TRUE_RESPONSE))
+}
+
+#[derive(Serialize)]
+pub struct RevokedSessions {
+    revoked: usize,
+}
+
+#[post("/sessions/logout-others")]
+pub async fn auth_logout_other_sessions(
+    identity: web::Data<Identity>,
+    auth_context: Option<web::ReqData<AuthenticattionInfoContext>>,
+) -> Result<impl Responder> {
+    let auth_context = auth_context.ok_or(actix_web::error::ErrorInternalServerError(
+        "Authentication info context not found in application",
+    ))?;
+
+    let revoked = identity
+        .revoke_other_sessions(&auth_context.auth_info)
+        .await?;
+
+    Ok(web::Json(RevokedSessions { revoked }))
+}
+
 #[post("/logout")]
 pub async fn logout(
     identity: web::Data<Identity>,
     token_context: Option<web::ReqData<AuthTokenContext>>,
 ) -> Result<HttpResponse> {
-    if token_context.is_none() {
-        return Ok(HttpResponse::Ok().finish());
-    }
-    let token = &token_context.unwrap().token;
-    identity.logout(token)?;
+    let token_context = token_context.ok_or(actix_web::error::ErrorUnauthorized(
+        "You are not authenticated",
+    ))?;
+    let token = &token_context.token;
+    identity.logout(token).await?;
     Ok(HttpResponse::Ok().finish())
 }
+
+#[get("/.well-known/jwks.json")]
+pub async fn jwks(identity: web::Data<Identity>) -> Result<impl Responder> {
+    let jwks = identity.jwks()?;
+    Ok(web::Json(jwks))
+}
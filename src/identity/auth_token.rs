@@ -12,7 +12,7 @@ use super::AuthTokenContext;
 // 1. Middleware initialization, middleware factory gets called with
 //    next service in chain as parameter.
 // 2. Middleware's call method gets called with normal request.
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct AuthTokenMiddlewareFactory;
 
 impl AuthTokenMiddlewareFactory {
@@ -94,7 +94,13 @@ where
         let auth_type = segments.next().unwrap();
         let auth_token = segments.next();
 
-        if auth_type != "Token" || auth_token.is_none() {
+        // client credentials of oauth endpoints are checked by handlers
+        if auth_type == "Basic" {
+            return Ok(());
+        }
+
+        // `Bearer` is used by clients of oauth flows
+        if !(auth_type == "Token" || auth_type == "Bearer") || auth_token.is_none() {
             return Err(actix_web::error::ErrorBadRequest(
                 "Invalid authorization info",
             ));
@@ -1,5 +1,6 @@
 use std::future::{ready, Future, Ready};
 use std::pin::Pin;
+use std::rc::Rc;
 
 use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
 use actix_web::web::Data;
@@ -8,12 +9,13 @@ use actix_web::{Error, HttpMessage};
 use super::{AuthTokenContext, AuthenticattionInfoContext, Identity};
 
 pub struct AuthorizationMiddleware<S> {
-    service: S,
+    service: Rc<S>,
+    restricted_paths: Rc<Vec<&'static str>>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthorizationMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -38,7 +40,7 @@ where
             });
         }
 
-        let identity = req.app_data::<Data<Identity>>();
+        let identity = req.app_data::<Data<Identity>>().cloned();
 
         if identity.is_none() {
             return Box::pin(async {
@@ -48,38 +50,53 @@ where
             });
         }
 
-        let auth_info = identity.unwrap().authorization_info(&auth_token.unwrap());
-        match auth_info {
-            Ok(auth_info) => {
-                req.extensions_mut()
-                    .insert(AuthenticattionInfoContext::new(auth_info.clone()));
+        let service = self.service.clone();
+        let restricted_allowed = self.restricted_paths.contains(&req.path());
 
-                let fut = self.service.call(req);
+        Box::pin(async move {
+            let identity = identity.unwrap();
+            let auth_token = auth_token.unwrap();
 
-                return Box::pin(async move {
-                    let res = fut.await?;
-                    Ok(res)
-                });
+            let auth_info = identity.authorization_info(&auth_token).await?;
+            if auth_info.restricted && !restricted_allowed {
+                return Err(actix_web::error::ErrorForbidden(
+                    "Parola este învechita; Schimbați parola",
+                ));
             }
-            Err(err) => {
-                return Box::pin(async { Err(err) });
-            }
-        };
+            identity.record_activity(&auth_token, &auth_info).await?;
+
+            req.extensions_mut()
+                .insert(AuthenticattionInfoContext::new(auth_info));
+
+            let res = service.call(req).await?;
+            Ok(res)
+        })
     }
 }
 
 #[derive(Clone)]
-pub struct Authorization {}
+pub struct Authorization {
+    /// paths reachable by restricted sessions of grace logins
+    restricted_paths: Rc<Vec<&'static str>>,
+}
 
 impl Authorization {
     pub fn enable() -> Self {
-        Self {}
+        Self {
+            restricted_paths: Rc::new(Vec::new()),
+        }
+    }
+
+    /// let restricted sessions reach `path` as well
+    pub fn allow_restricted(mut self, path: &'static str) -> Self {
+        Rc::make_mut(&mut self.restricted_paths).push(path);
+        self
     }
 }
 
 impl<S, B> Transform<S, ServiceRequest> for Authorization
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -90,6 +107,9 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(AuthorizationMiddleware { service }))
+        ready(Ok(AuthorizationMiddleware {
+            service: Rc::new(service),
+            restricted_paths: self.restricted_paths.clone(),
+        }))
     }
 }
@@ -0,0 +1,280 @@
+use std::num::NonZeroU32;
+
+use argon2::password_hash::{PasswordHash, SaltString};
+use argon2::{Algorithm, Argon2, Params, PasswordHasher as _, PasswordVerifier as _, Version};
+use base64::{decode, encode};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, pbkdf2};
+
+use crate::domain;
+use crate::setup::{PasswordAlgorithm, PasswordConfig};
+
+static PBKDF2_ALG: pbkdf2::Algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
+
+const CREDENTIAL_LEN: usize = digest::SHA256_OUTPUT_LEN;
+type Credential = [u8; CREDENTIAL_LEN];
+
+const SALT_LEN: usize = 16;
+
+/// Password as stored in `security.users`:
+/// a PHC string in `password`, or a legacy base64 PBKDF2-SHA256 hash with its `salt` and cost
+pub struct HashedPassword {
+    /// empty for PHC strings, which carry their salt
+    pub salt: String,
+    pub password: String,
+    /// PBKDF2 iterations of legacy hashes; 0 for PHC strings
+    pub iterations: i32,
+}
+
+impl From<&domain::User> for HashedPassword {
+    /// current password of the user
+    fn from(user: &domain::User) -> Self {
+        Self {
+            salt: user.salt.clone(),
+            password: user.password.clone(),
+            iterations: user.password_iterations,
+        }
+    }
+}
+
+impl From<&domain::PasswordHistoryEntry> for HashedPassword {
+    fn from(entry: &domain::PasswordHistoryEntry) -> Self {
+        Self {
+            salt: entry.salt.clone(),
+            password: entry.password.clone(),
+            iterations: entry.password_iterations,
+        }
+    }
+}
+
+/// Hashes new passwords with the configured algorithm;
+/// verifies Argon2id PHC strings and legacy PBKDF2 hashes
+#[derive(Clone)]
+pub struct PasswordHasher {
+    algorithm: PasswordAlgorithm,
+    pbkdf2_iterations: NonZeroU32,
+    argon2: Argon2<'static>,
+}
+
+impl PasswordHasher {
+    pub fn new(config: &PasswordConfig) -> Self {
+        let params = Params::new(
+            config.argon2_memory_kib,
+            config.argon2_iterations,
+            config.argon2_parallelism,
+            None,
+        )
+        .expect("invalid Argon2 parameters");
+        Self {
+            algorithm: config.algorithm,
+            pbkdf2_iterations: NonZeroU32::new(config.pbkdf2_iterations)
+                .expect("PBKDF2 iterations must be positive"),
+            argon2: Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+        }
+    }
+
+    pub fn hash(&self, password: &str) -> HashedPassword {
+        match self.algorithm {
+            PasswordAlgorithm::Argon2id => {
+                let salt = SaltString::encode_b64(&random_salt()).unwrap();
+                let hash = self
+                    .argon2
+                    .hash_password(password.as_bytes(), &salt)
+                    .expect("Argon2 hashing failed");
+                HashedPassword {
+                    salt: String::new(),
+                    password: hash.to_string(),
+                    iterations: 0,
+                }
+            }
+            PasswordAlgorithm::Pbkdf2 => {
+                let salt = random_salt();
+                let mut to_store: Credential = [0u8; CREDENTIAL_LEN];
+                pbkdf2::derive(
+                    PBKDF2_ALG,
+                    self.pbkdf2_iterations,
+                    &salt,
+                    password.as_bytes(),
+                    &mut to_store,
+                );
+                HashedPassword {
+                    salt: encode(salt),
+                    password: encode(to_store),
+                    iterations: self.pbkdf2_iterations.get() as i32,
+                }
+            }
+        }
+    }
+
+    /// checks `attempted` against a stored hash, with the algorithm and parameters it was made with;
+    /// malformed hashes match nothing
+    pub fn verify(&self, salt: &str, password: &str, iterations: i32, attempted: &str) -> bool {
+        if is_phc(password) {
+            return match PasswordHash::new(password) {
+                // parameters are taken from the hash, not from `self.argon2`
+                Ok(hash) => Argon2::default()
+                    .verify_password(attempted.as_bytes(), &hash)
+                    .is_ok(),
+                Err(err) => {
+                    log::error!("malformed password hash: {}", err);
+                    false
+                }
+            };
+        }
+
+        let iterations = match u32::try_from(iterations).ok().and_then(NonZeroU32::new) {
+            Some(iterations) => iterations,
+            None => {
+                log::error!("invalid PBKDF2 iterations of password hash: {}", iterations);
+                return false;
+            }
+        };
+        let (salt, password) = match (decode(salt), decode(password)) {
+            (Ok(salt), Ok(password)) => (salt, password),
+            _ => {
+                log::error!("malformed PBKDF2 password hash");
+                return false;
+            }
+        };
+        pbkdf2::verify(
+            PBKDF2_ALG,
+            iterations,
+            &salt,
+            attempted.as_bytes(),
+            &password,
+        )
+        .is_ok()
+    }
+
+    /// stored hash differs from new hashes in algorithm or cost
+    pub fn needs_rehash(&self, password: &str, iterations: i32) -> bool {
+        match self.algorithm {
+            PasswordAlgorithm::Argon2id => match PasswordHash::new(password) {
+                Ok(hash) if hash.algorithm == Algorithm::Argon2id.ident() => {
+                    hash.version != Some(Version::V0x13.into())
+                        || Params::try_from(&hash).map_or(true, |params| {
+                            let current = self.argon2.params();
+                            params.m_cost() != current.m_cost()
+                                || params.t_cost() != current.t_cost()
+                                || params.p_cost() != current.p_cost()
+                        })
+                }
+                _ => true,
+            },
+            PasswordAlgorithm::Pbkdf2 => {
+                is_phc(password) || iterations != self.pbkdf2_iterations.get() as i32
+            }
+        }
+    }
+}
+
+/// PHC strings start with `$<algorithm>`; legacy hashes are plain base64
+fn is_phc(password: &str) -> bool {
+    password.starts_with('$')
+}
+
+fn random_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0u8; SALT_LEN];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .expect("system random generator failed");
+    salt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// cheap parameters; the hashes are only checked for format and matching
+    fn hasher(algorithm: PasswordAlgorithm, argon2_memory_kib: u32) -> PasswordHasher {
+        PasswordHasher::new(&PasswordConfig {
+            algorithm,
+            pbkdf2_iterations: 1000,
+            argon2_memory_kib,
+            argon2_iterations: 1,
+            argon2_parallelism: 1,
+            ..PasswordConfig::default()
+        })
+    }
+
+    // PBKDF2-SHA256 of "parola-veche" with salt "0123456789abcdef", 1000 iterations
+    const LEGACY_SALT: &str = "MDEyMzQ1Njc4OWFiY2RlZg==";
+    const LEGACY_HASH: &str = "LTvzelsk+K5oB8aSii109aoc0mEwe+zb53IcCmeHI6k=";
+
+    #[test]
+    fn argon2_hash_is_phc_string() {
+        let hashed = hasher(PasswordAlgorithm::Argon2id, 1024).hash("parola-noua");
+        assert!(hashed
+            .password
+            .starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert!(hashed.salt.is_empty());
+        assert_eq!(hashed.iterations, 0);
+    }
+
+    #[test]
+    fn argon2_hash_verifies() {
+        let hasher = hasher(PasswordAlgorithm::Argon2id, 1024);
+        let hashed = hasher.hash("parola-noua");
+        assert!(hasher.verify("", &hashed.password, 0, "parola-noua"));
+        assert!(!hasher.verify("", &hashed.password, 0, "parola-veche"));
+    }
+
+    #[test]
+    fn argon2_parameters_come_from_the_hash() {
+        let hashed = hasher(PasswordAlgorithm::Argon2id, 2048).hash("parola-noua");
+        let other = hasher(PasswordAlgorithm::Pbkdf2, 1024);
+        assert!(other.verify("", &hashed.password, 0, "parola-noua"));
+    }
+
+    #[test]
+    fn legacy_pbkdf2_hash_verifies() {
+        let hasher = hasher(PasswordAlgorithm::Argon2id, 1024);
+        assert!(hasher.verify(LEGACY_SALT, LEGACY_HASH, 1000, "parola-veche"));
+        assert!(!hasher.verify(LEGACY_SALT, LEGACY_HASH, 1000, "parola-noua"));
+        assert!(!hasher.verify(LEGACY_SALT, LEGACY_HASH, 1001, "parola-veche"));
+    }
+
+    #[test]
+    fn pbkdf2_hash_verifies() {
+        let hasher = hasher(PasswordAlgorithm::Pbkdf2, 1024);
+        let hashed = hasher.hash("parola-noua");
+        assert_eq!(hashed.iterations, 1000);
+        assert!(hasher.verify(
+            &hashed.salt,
+            &hashed.password,
+            hashed.iterations,
+            "parola-noua"
+        ));
+        assert!(!hasher.verify(
+            &hashed.salt,
+            &hashed.password,
+            hashed.iterations,
+            "parola-veche"
+        ));
+    }
+
+    #[test]
+    fn malformed_hashes_match_nothing() {
+        let hasher = hasher(PasswordAlgorithm::Argon2id, 1024);
+        assert!(!hasher.verify("", "$argon2id$broken", 0, "parola-veche"));
+        assert!(!hasher.verify(LEGACY_SALT, LEGACY_HASH, 0, "parola-veche"));
+        assert!(!hasher.verify(LEGACY_SALT, LEGACY_HASH, -1, "parola-veche"));
+        assert!(!hasher.verify("not base64!", LEGACY_HASH, 1000, "parola-veche"));
+        assert!(!hasher.verify(LEGACY_SALT, "not base64!", 1000, "parola-veche"));
+    }
+
+    #[test]
+    fn rehash_on_other_algorithm_or_cost() {
+        let argon2 = hasher(PasswordAlgorithm::Argon2id, 1024);
+        let pbkdf2 = hasher(PasswordAlgorithm::Pbkdf2, 1024);
+        let phc = argon2.hash("parola-noua").password;
+
+        assert!(!argon2.needs_rehash(&phc, 0));
+        assert!(hasher(PasswordAlgorithm::Argon2id, 2048).needs_rehash(&phc, 0));
+        assert!(argon2.needs_rehash(LEGACY_HASH, 1000));
+
+        assert!(!pbkdf2.needs_rehash(LEGACY_HASH, 1000));
+        assert!(pbkdf2.needs_rehash(LEGACY_HASH, 500));
+        assert!(pbkdf2.needs_rehash(&phc, 0));
+    }
+}
@@ -0,0 +1,248 @@
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use chrono::{DateTime, Duration, Utc};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::sign::Signer;
+use ring::digest;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::AuthenticatedUser;
+use crate::domain;
+
+/// Issues access tokens and OpenID Connect id tokens as JWT signed by a key from keystore,
+/// so that other services may verify them offline
+pub struct JwtIssuer {
+    key: PKey<Private>,
+    algorithm: &'static str,
+    key_id: String,
+    issuer: String,
+    lifetime: Duration,
+}
+
+#[derive(Serialize)]
+struct AccessClaims<'a> {
+    iss: &'a str,
+    sub: String,
+    iat: i64,
+    exp: i64,
+    jti: Uuid,
+    sid: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    personnel_nr: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<&'a str>,
+    username: &'a str,
+    roles: Arc<Vec<domain::UserRole>>,
+    resources: Arc<Vec<domain::UserResource>>,
+}
+
+/// OpenID Connect claims of the user, in `id_token` and from `/userinfo`
+#[derive(Serialize)]
+pub struct UserClaims<'a> {
+    sub: String,
+    preferred_username: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phone_number: Option<&'a str>,
+    roles: Vec<String>,
+}
+
+impl<'a> From<&'a AuthenticatedUser> for UserClaims<'a> {
+    fn from(auth_info: &'a AuthenticatedUser) -> Self {
+        Self {
+            sub: auth_info.subject(),
+            preferred_username: &auth_info.user.username,
+            email: auth_info.user.email.as_deref(),
+            phone_number: auth_info.user.telefon.as_deref(),
+            roles: auth_info
+                .roles()
+                .iter()
+                .map(|role| role.role_name.clone())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct IdTokenClaims<'a> {
+    iss: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+    auth_time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<&'a str>,
+    #[serde(flatten)]
+    user: UserClaims<'a>,
+}
+
+impl JwtIssuer {
+    /// supported keys: RSA (RS256) and EC P-256 (ES256)
+    pub fn new(key: PKey<Private>, issuer: String, lifetime: Duration) -> Result<Self, String> {
+        let algorithm = match key.id() {
+            Id::RSA => "RS256",
+            Id::EC => {
+                let curve = key.ec_key().ok().and_then(|it| it.group().curve_name());
+                if curve != Some(Nid::X9_62_PRIME256V1) {
+                    return Err("only P-256 EC keys are supported".to_owned());
+                }
+                "ES256"
+            }
+            _ => return Err("only RSA and EC keys are supported".to_owned()),
+        };
+
+        let public_key = key.public_key_to_der().map_err(|e| e.to_string())?;
+        let fingerprint = digest::digest(&digest::SHA256, &public_key);
+        let key_id = encode_config(&fingerprint.as_ref()[..12], URL_SAFE_NO_PAD);
+
+        Ok(Self {
+            key,
+            algorithm,
+            key_id,
+            issuer,
+            lifetime,
+        })
+    }
+
+    /// signed access token for the session of `auth_info`; it can't be revoked,
+    /// so it expires no later than the access token of the session, in `expires_in`
+    pub fn issue(
+        &self,
+        auth_info: &AuthenticatedUser,
+        now: DateTime<Utc>,
+        expires_in: Duration,
+    ) -> Result<String, actix_web::Error> {
+        let header = json!({
+            "alg": self.algorithm,
+            "typ": "JWT",
+            "kid": self.key_id,
+        });
+        let claims = AccessClaims {
+            iss: &self.issuer,
+            sub: auth_info.subject(),
+            iat: now.timestamp(),
+            exp: (now + self.lifetime.min(expires_in)).timestamp(),
+            jti: Uuid::new_v4(),
+            sid: auth_info.id,
+            personnel_nr: (!auth_info.is_service()).then_some(auth_info.user.personnel_nr),
+            client_id: auth_info.client_id.as_deref(),
+            username: &auth_info.user.username,
+            roles: auth_info.roles(),
+            resources: auth_info.resources(),
+        };
+        self.sign(&header, &claims)
+    }
+
+    /// OpenID Connect id token for the oauth client `audience`
+    pub fn issue_id_token(
+        &self,
+        auth_info: &AuthenticatedUser,
+        audience: &str,
+        nonce: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<String, actix_web::Error> {
+        let header = json!({
+            "alg": self.algorithm,
+            "typ": "JWT",
+            "kid": self.key_id,
+        });
+        let claims = IdTokenClaims {
+            iss: &self.issuer,
+            aud: audience,
+            iat: now.timestamp(),
+            exp: (now + self.lifetime).timestamp(),
+            auth_time: auth_info.authenticated.read().unwrap().timestamp(),
+            nonce,
+            user: UserClaims::from(auth_info),
+        };
+        self.sign(&header, &claims)
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn algorithm(&self) -> &'static str {
+        self.algorithm
+    }
+
+    fn sign<C: Serialize>(&self, header: &Value, claims: &C) -> Result<String, actix_web::Error> {
+        let header = serde_json::to_vec(header)?;
+        let claims = serde_json::to_vec(claims)?;
+        let signing_input = format!(
+            "{}.{}",
+            encode_config(header, URL_SAFE_NO_PAD),
+            encode_config(claims, URL_SAFE_NO_PAD)
+        );
+
+        let signature = self
+            .raw_signature(signing_input.as_bytes())
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            encode_config(signature, URL_SAFE_NO_PAD)
+        ))
+    }
+
+    fn raw_signature(&self, data: &[u8]) -> Result<Vec<u8>, openssl::error::ErrorStack> {
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key)?;
+        let signature = signer.sign_oneshot_to_vec(data)?;
+        if self.key.id() != Id::EC {
+            return Ok(signature);
+        }
+
+        // JWS uses fixed size R || S instead of DER for ECDSA
+        let signature = EcdsaSig::from_der(&signature)?;
+        let mut raw = signature.r().to_vec_padded(32)?;
+        raw.extend(signature.s().to_vec_padded(32)?);
+        Ok(raw)
+    }
+
+    /// public key as JSON Web Key Set
+    pub fn jwks(&self) -> Result<Value, openssl::error::ErrorStack> {
+        let jwk = match self.key.id() {
+            Id::EC => {
+                let ec_key = self.key.ec_key()?;
+                let mut ctx = BigNumContext::new()?;
+                let mut x = BigNum::new()?;
+                let mut y = BigNum::new()?;
+                ec_key.public_key().affine_coordinates_gfp(
+                    ec_key.group(),
+                    &mut x,
+                    &mut y,
+                    &mut ctx,
+                )?;
+                json!({
+                    "kty": "EC",
+                    "crv": "P-256",
+                    "x": encode_config(x.to_vec_padded(32)?, URL_SAFE_NO_PAD),
+                    "y": encode_config(y.to_vec_padded(32)?, URL_SAFE_NO_PAD),
+                    "use": "sig",
+                    "alg": self.algorithm,
+                    "kid": self.key_id,
+                })
+            }
+            _ => {
+                let rsa = self.key.rsa()?;
+                json!({
+                    "kty": "RSA",
+                    "n": encode_config(rsa.n().to_vec(), URL_SAFE_NO_PAD),
+                    "e": encode_config(rsa.e().to_vec(), URL_SAFE_NO_PAD),
+                    "use": "sig",
+                    "alg": self.algorithm,
+                    "kid": self.key_id,
+                })
+            }
+        };
+        Ok(json!({ "keys": [jwk] }))
+    }
+}
@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use actix_web::rt;
+use deadpool_postgres::Pool;
+use futures_util::future::{self, Either};
+use futures_util::{stream, try_join, StreamExt};
+use postgres_openssl::MakeTlsConnector;
+use tokio_postgres::AsyncMessage;
+
+use super::Identity;
+use crate::database::{find_active_users, load_user_resources, load_user_roles};
+
+const CHANNEL: &str = "security_changes";
+
+/// pause before reconnecting a lost listener connection
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Change of a user announced by database triggers
+enum Change {
+    /// user dismissed, disabled or deleted
+    Revoke(i16),
+    /// roles or resources of user changed
+    Permissions(i16),
+}
+
+impl Change {
+    fn parse(payload: &str) -> Option<Self> {
+        let (kind, personnel_nr) = payload.split_once(':')?;
+        let personnel_nr = personnel_nr.parse().ok()?;
+        match kind {
+            "revoke" => Some(Change::Revoke(personnel_nr)),
+            "permissions" => Some(Change::Permissions(personnel_nr)),
+            _ => None,
+        }
+    }
+}
+
+/// listens on a dedicated connection for changes of users and their permissions,
+/// and applies them to live sessions right away
+pub fn spawn_change_listener(
+    identity: Identity,
+    pool: Pool,
+    pg_config: tokio_postgres::Config,
+    tls: MakeTlsConnector,
+) {
+    rt::spawn(async move {
+        loop {
+            match listen(&identity, &pool, &pg_config, tls.clone()).await {
+                Ok(()) => log::warn!("listener connection closed"),
+                Err(err) => log::error!("listener connection failed: {}", err),
+            }
+            rt::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+async fn listen(
+    identity: &Identity,
+    pool: &Pool,
+    pg_config: &tokio_postgres::Config,
+    tls: MakeTlsConnector,
+) -> Result<(), tokio_postgres::Error> {
+    let (client, mut connection) = pg_config.connect(tls).await?;
+    let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+
+    // LISTEN completes only while the connection is polled
+    let statement = format!("LISTEN {}", CHANNEL);
+    let mut listening = Box::pin(client.batch_execute(&statement));
+    loop {
+        match future::select(listening, messages.next()).await {
+            Either::Left((result, _)) => {
+                result?;
+                break;
+            }
+            Either::Right((message, pending)) => {
+                match message {
+                    Some(Err(err)) => return Err(err),
+                    None => return Ok(()),
+                    Some(Ok(_)) => {}
+                }
+                listening = pending;
+            }
+        }
+    }
+    log::info!("listening on {} for session invalidation", CHANNEL);
+
+    // changes while disconnected were not announced
+    match revoke_inactive_users(identity, pool).await {
+        Ok(0) => {}
+        Ok(revoked) => log::info!(
+            "revoked {} sessions of users disabled or dismissed while not listening",
+            revoked
+        ),
+        Err(err) => log::error!("failed to revoke sessions of inactive users: {}", err),
+    }
+
+    while let Some(message) = messages.next().await {
+        if let AsyncMessage::Notification(notification) = message? {
+            match Change::parse(notification.payload()) {
+                Some(change) => apply(identity, pool, change).await,
+                None => log::warn!("unknown change: {}", notification.payload()),
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn apply(identity: &Identity, pool: &Pool, change: Change) {
+    match change {
+        Change::Revoke(personnel_nr) => {
+            match identity.revoke_sessions_of_user(personnel_nr).await {
+                Ok(removed) => log::info!("revoked {} sessions of user {}", removed, personnel_nr),
+                Err(err) => log::error!(
+                    "failed to revoke sessions of user {}: {}",
+                    personnel_nr,
+                    err
+                ),
+            }
+        }
+        Change::Permissions(personnel_nr) => {
+            match reload_permissions(identity, pool, personnel_nr).await {
+                Ok(updated) => log::info!(
+                    "reloaded permissions of user {} in {} sessions",
+                    personnel_nr,
+                    updated
+                ),
+                Err(err) => log::error!(
+                    "failed to reload permissions of user {}: {}",
+                    personnel_nr,
+                    err
+                ),
+            }
+        }
+    }
+}
+
+/// closes sessions of users who are disabled, dismissed or deleted by now;
+/// returns count of closed sessions
+async fn revoke_inactive_users(
+    identity: &Identity,
+    pool: &Pool,
+) -> Result<usize, actix_web::Error> {
+    let owners = identity.session_owners().await?;
+    if owners.is_empty() {
+        return Ok(0);
+    }
+    let client = pool
+        .get()
+        .await
+        .map_err(crate::errors::DatabaseError::PoolError)?;
+    let active = find_active_users(&client, &owners).await?;
+
+    let mut revoked = 0;
+    for personnel_nr in owners.into_iter().filter(|it| !active.contains(it)) {
+        revoked += identity.revoke_sessions_of_user(personnel_nr).await?;
+    }
+    Ok(revoked)
+}
+
+async fn reload_permissions(
+    identity: &Identity,
+    pool: &Pool,
+    personnel_nr: i16,
+) -> Result<usize, actix_web::Error> {
+    let client = pool
+        .get()
+        .await
+        .map_err(crate::errors::DatabaseError::PoolError)?;
+    let roles = load_user_roles(&client, personnel_nr);
+    let resources = load_user_resources(&client, personnel_nr);
+    let (roles, resources) = try_join!(roles, resources)?;
+    identity
+        .reload_permissions_of_user(personnel_nr, roles, resources)
+        .await
+}
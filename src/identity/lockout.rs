@@ -0,0 +1,101 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::setup::LockoutConfig;
+
+/// longest doubling of the base lockout; later failures keep the maximal lockout
+const MAX_DOUBLINGS: i32 = 30;
+
+/// Lockout of accounts after failed logins, see `setup::LockoutConfig`
+#[derive(Clone, Copy)]
+pub struct LockoutPolicy {
+    threshold: Option<i32>,
+    base: Duration,
+    max: Duration,
+}
+
+impl LockoutPolicy {
+    pub fn new(config: &LockoutConfig) -> Self {
+        Self {
+            threshold: Some(config.threshold).filter(|threshold| *threshold > 0),
+            base: Duration::seconds(config.base_lockout_secs),
+            max: Duration::seconds(config.max_lockout_secs),
+        }
+    }
+
+    /// end of the lockout after `failures` consecutive failed logins at `now`:
+    /// none below the threshold, then the base lockout, doubled by every further failure
+    pub fn locked_until(&self, failures: i32, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let threshold = self.threshold?;
+        if failures < threshold {
+            return None;
+        }
+        let doublings = (failures - threshold).min(MAX_DOUBLINGS);
+        let lockout = self.base.num_seconds().saturating_mul(1 << doublings);
+        // clamped before the conversion, which panics beyond the range of `Duration`
+        let lockout = Duration::seconds(lockout.min(self.max.num_seconds()));
+        Some(
+            now.checked_add_signed(lockout)
+                .unwrap_or(DateTime::<Utc>::MAX_UTC),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(threshold: i32) -> LockoutPolicy {
+        LockoutPolicy::new(&LockoutConfig {
+            threshold,
+            base_lockout_secs: 60,
+            max_lockout_secs: 60 * 60,
+        })
+    }
+
+    /// lockout in seconds after `failures`
+    fn lockout_secs(policy: &LockoutPolicy, failures: i32) -> Option<i64> {
+        let now = Utc::now();
+        policy
+            .locked_until(failures, now)
+            .map(|until| (until - now).num_seconds())
+    }
+
+    #[test]
+    fn no_lockout_below_threshold() {
+        let policy = policy(5);
+        for failures in 0..5 {
+            assert_eq!(lockout_secs(&policy, failures), None);
+        }
+    }
+
+    #[test]
+    fn lockout_doubles_after_threshold() {
+        let policy = policy(5);
+        assert_eq!(lockout_secs(&policy, 5), Some(60));
+        assert_eq!(lockout_secs(&policy, 6), Some(120));
+        assert_eq!(lockout_secs(&policy, 7), Some(240));
+        assert_eq!(lockout_secs(&policy, 10), Some(1920));
+    }
+
+    #[test]
+    fn lockout_is_capped_at_max() {
+        let policy = policy(5);
+        assert_eq!(lockout_secs(&policy, 11), Some(3600));
+        assert_eq!(lockout_secs(&policy, 100), Some(3600));
+        assert_eq!(lockout_secs(&policy, i32::MAX), Some(3600));
+    }
+
+    #[test]
+    fn lockout_is_capped_near_the_end_of_time() {
+        let policy = policy(1);
+        let now = DateTime::<Utc>::MAX_UTC - Duration::seconds(10);
+        assert_eq!(policy.locked_until(1, now), Some(DateTime::<Utc>::MAX_UTC));
+    }
+
+    #[test]
+    fn zero_threshold_disables_lockout() {
+        let policy = policy(0);
+        assert_eq!(lockout_secs(&policy, 0), None);
+        assert_eq!(lockout_secs(&policy, 1000), None);
+    }
+}
@@ -1,6 +1,16 @@
 mod auth_token;
 mod authorization;
+mod hasher;
+mod jwt;
+mod listener;
+mod lockout;
+mod password;
+mod policy;
+mod rate_limit;
+mod reaper;
 mod service;
+mod store;
+mod token;
 
 use chrono::{DateTime, Utc};
 use serde::Serialize;
@@ -22,16 +32,90 @@ pub struct AuthenticattionInfoContext {
 
 #[derive(Serialize)]
 pub struct AuthenticatedUser {
+    #[serde(rename = "session_id")]
+    pub id: Uuid,
     pub user: crate::domain::User,
-    pub roles: Arc<Vec<crate::domain::UserRole>>,
-    pub resources: Arc<Vec<crate::domain::UserResource>>,
+    /// set for sessions of service clients; `user` is then only a placeholder
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// oauth client the tokens of the session were issued to by the authorization code grant;
+    /// only this client may redeem its refresh tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issued_to: Option<String>,
+    /// session of a grace login with an expired password; may only change the password
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub restricted: bool,
+    /// swapped when permissions are reloaded
+    roles: RwLock<Arc<Vec<crate::domain::UserRole>>>,
+    resources: RwLock<Arc<Vec<crate::domain::UserResource>>>,
+    #[serde(flatten)]
+    pub device: SessionDevice,
+    pub created: DateTime<Utc>,
     authenticated: RwLock<DateTime<Utc>>,
+    last_seen: RwLock<DateTime<Utc>>,
+    /// when the current auth token was issued
+    #[serde(skip)]
+    token_issued: RwLock<DateTime<Utc>>,
+}
+
+/// Device a session was opened from
+#[derive(Serialize, Clone, Default)]
+pub struct SessionDevice {
+    pub user_agent: Option<String>,
+    pub client_ip: Option<String>,
+}
+
+/// Active session as shown to its owner
+#[derive(Serialize)]
+pub struct SessionSummary {
+    pub id: Uuid,
+    pub created: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    #[serde(flatten)]
+    pub device: SessionDevice,
+    pub current: bool,
+}
+
+/// Kinds of tokens issued for a session
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TokenType {
+    AccessToken,
+    RefreshToken,
+}
+
+impl TokenType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenType::AccessToken => "access_token",
+            TokenType::RefreshToken => "refresh_token",
+        }
+    }
+}
+
+/// Active auth token with its session
+pub struct TokenInfo {
+    pub auth_info: Arc<AuthenticatedUser>,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
 }
 
 #[derive(Serialize, Clone)]
 pub struct AuthenticationResponse {
-    token: Uuid,
-    auth_info: Arc<AuthenticatedUser>,
+    pub token: String,
+    /// seconds until `token` expires
+    pub expires_in: i64,
+    /// not issued to service clients
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// signed JWT, when enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    /// days until the password expires, negative once it has expired; not set for service clients
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_expires_in_days: Option<i64>,
+    /// session is restricted to changing the expired password
+    pub must_change_password: bool,
+    pub auth_info: Arc<AuthenticatedUser>,
 }
 
 impl AuthTokenContext {
@@ -42,6 +126,139 @@ impl AuthTokenContext {
     }
 }
 
+impl AuthenticatedUser {
+    pub fn new(
+        user: crate::domain::User,
+        roles: Vec<crate::domain::UserRole>,
+        resources: Vec<crate::domain::UserResource>,
+        device: SessionDevice,
+        created: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user,
+            client_id: None,
+            issued_to: None,
+            restricted: false,
+            roles: RwLock::new(Arc::new(roles)),
+            resources: RwLock::new(Arc::new(resources)),
+            device,
+            created,
+            authenticated: RwLock::new(created),
+            last_seen: RwLock::new(created),
+            token_issued: RwLock::new(created),
+        }
+    }
+
+    /// session of a service client, authorized with the roles and resources of the client
+    pub fn service(
+        client: &crate::domain::OAuthClient,
+        roles: Vec<crate::domain::UserRole>,
+        resources: Vec<crate::domain::UserResource>,
+        device: SessionDevice,
+        created: DateTime<Utc>,
+    ) -> Self {
+        let user = crate::domain::User {
+            personnel_nr: SERVICE_PERSONNEL_NR,
+            salt: String::new(),
+            password: String::new(),
+            password_iterations: 0,
+            password_expiration_date: chrono::NaiveDate::MAX,
+            username: client.name.clone(),
+            account_disabled: false,
+            date_dismiss: None,
+            telefon: None,
+            email: None,
+        };
+        Self {
+            client_id: Some(client.client_id.clone()),
+            ..Self::new(user, roles, resources, device, created)
+        }
+    }
+
+    /// session of a grace login
+    pub fn into_restricted(self) -> Self {
+        Self {
+            restricted: true,
+            ..self
+        }
+    }
+
+    /// session opened for an oauth client
+    pub fn into_issued_to(self, client_id: &str) -> Self {
+        Self {
+            issued_to: Some(client_id.to_owned()),
+            ..self
+        }
+    }
+
+    pub fn roles(&self) -> Arc<Vec<crate::domain::UserRole>> {
+        self.roles.read().unwrap().clone()
+    }
+
+    pub fn resources(&self) -> Arc<Vec<crate::domain::UserResource>> {
+        self.resources.read().unwrap().clone()
+    }
+
+    /// replace roles and resources of the live session
+    pub fn set_permissions(
+        &self,
+        roles: Arc<Vec<crate::domain::UserRole>>,
+        resources: Arc<Vec<crate::domain::UserResource>>,
+    ) {
+        *self.roles.write().unwrap() = roles;
+        *self.resources.write().unwrap() = resources;
+    }
+
+    pub fn is_service(&self) -> bool {
+        self.client_id.is_some()
+    }
+
+    /// owner of the session; none for service clients
+    pub fn personnel_nr(&self) -> Option<i16> {
+        (!self.is_service()).then_some(self.user.personnel_nr)
+    }
+
+    /// `sub` of issued tokens: client id of service clients, personnel nr of users
+    pub fn subject(&self) -> String {
+        match &self.client_id {
+            Some(client_id) => client_id.clone(),
+            None => self.user.personnel_nr.to_string(),
+        }
+    }
+}
+
+/// personnel nr of the placeholder user of service clients; never stored
+const SERVICE_PERSONNEL_NR: i16 = 0;
+
+impl From<&AuthenticatedUser> for SessionSummary {
+    fn from(auth_user: &AuthenticatedUser) -> Self {
+        Self {
+            id: auth_user.id,
+            created: auth_user.created,
+            last_seen: *auth_user.last_seen.read().unwrap(),
+            device: auth_user.device.clone(),
+            current: false,
+        }
+    }
+}
+
+impl SessionDevice {
+    pub fn of(req: &actix_web::HttpRequest) -> Self {
+        Self {
+            user_agent: req
+                .headers()
+                .get(actix_web::http::header::USER_AGENT)
+                .and_then(|it| it.to_str().ok())
+                .map(str::to_owned),
+            client_ip: req
+                .connection_info()
+                .realip_remote_addr()
+                .map(str::to_owned),
+        }
+    }
+}
+
 impl AuthenticattionInfoContext {
     pub fn new(auth_info: Arc<AuthenticatedUser>) -> Self {
         Self { auth_info }
@@ -50,4 +267,14 @@ impl AuthenticattionInfoContext {
 
 pub use auth_token::AuthTokenMiddlewareFactory;
 pub use authorization::Authorization;
-pub use service::Identity;
+pub use hasher::{HashedPassword, PasswordHasher};
+pub use jwt::{JwtIssuer, UserClaims};
+pub use listener::spawn_change_listener;
+pub use lockout::LockoutPolicy;
+pub use password::{PasswordContext, PasswordPolicy, PolicyViolation};
+pub use policy::{Expiration, SessionLimit, SessionPolicy};
+pub use rate_limit::{Quota, RateLimiter};
+pub use reaper::spawn_session_reaper;
+pub use service::{parse_reset_code, Identity};
+pub use store::{MemorySessionStore, PgSessionStore, RemovedSession, Rotation, SessionStore};
+pub use token::{redact_reset_codes, TokenDigest};
@@ -0,0 +1,415 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use super::hasher::{HashedPassword, PasswordHasher};
+use crate::domain;
+use crate::errors::PasswordPolicyError;
+use crate::locale::Language;
+use crate::setup::PasswordConfig;
+
+/// identifiers shorter than this are not searched for in passwords
+const MIN_IDENTITY_LEN: usize = 3;
+
+/// What a new password is checked against, besides itself
+pub struct PasswordContext<'a> {
+    pub user: &'a domain::User,
+    /// hashes the new password must not match: the current password and older ones
+    pub previous: &'a [HashedPassword],
+}
+
+/// Reason a password was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyViolation {
+    TooShort {
+        min_length: usize,
+    },
+    TooFewCharacterClasses {
+        required: usize,
+    },
+    ContainsUserIdentity,
+    Breached,
+    /// matches one of the last `passwords` passwords, the current one included
+    Reused {
+        passwords: usize,
+    },
+}
+
+impl PolicyViolation {
+    /// stable identifier for clients
+    pub fn code(&self) -> &'static str {
+        match self {
+            PolicyViolation::TooShort { .. } => "too_short",
+            PolicyViolation::TooFewCharacterClasses { .. } => "too_few_character_classes",
+            PolicyViolation::ContainsUserIdentity => "contains_user_identity",
+            PolicyViolation::Breached => "breached",
+            PolicyViolation::Reused { .. } => "reused",
+        }
+    }
+
+    pub fn message(&self, language: Language) -> String {
+        match (self, language) {
+            (PolicyViolation::TooShort { min_length }, Language::Ro) => {
+                format!("Parola trebuie să conțină cel puțin {} caractere", min_length)
+            }
+            (PolicyViolation::TooShort { min_length }, Language::En) => {
+                format!("Password must contain at least {} characters", min_length)
+            }
+            (PolicyViolation::TooFewCharacterClasses { required }, Language::Ro) => format!(
+                "Parola trebuie să conțină cel puțin {} din: litere mici, litere mari, cifre, alte simboluri",
+                required
+            ),
+            (PolicyViolation::TooFewCharacterClasses { required }, Language::En) => format!(
+                "Password must contain at least {} of: lowercase letters, uppercase letters, digits, other symbols",
+                required
+            ),
+            (PolicyViolation::ContainsUserIdentity, Language::Ro) => {
+                "Parola nu poate conține numele de utilizator sau numărul de personal".to_owned()
+            }
+            (PolicyViolation::ContainsUserIdentity, Language::En) => {
+                "Password must not contain the username or personnel number".to_owned()
+            }
+            (PolicyViolation::Breached, Language::Ro) => {
+                "Parola este prea comună sau a fost compromisă".to_owned()
+            }
+            (PolicyViolation::Breached, Language::En) => {
+                "Password is too common or was found in a breach".to_owned()
+            }
+            (PolicyViolation::Reused { passwords }, Language::Ro) => format!(
+                "Parola a mai fost folosită; alegeți una diferită de ultimele {} parole",
+                passwords
+            ),
+            (PolicyViolation::Reused { passwords }, Language::En) => format!(
+                "Password was used before; choose one different from the last {} passwords",
+                passwords
+            ),
+        }
+    }
+}
+
+/// One rule of the password policy
+pub trait PasswordRule: Send + Sync {
+    fn check(&self, password: &str, context: &PasswordContext) -> Option<PolicyViolation>;
+}
+
+struct MinLength(usize);
+
+impl PasswordRule for MinLength {
+    fn check(&self, password: &str, _: &PasswordContext) -> Option<PolicyViolation> {
+        (password.chars().count() < self.0)
+            .then_some(PolicyViolation::TooShort { min_length: self.0 })
+    }
+}
+
+/// at least this many of: lowercase, uppercase, digits, other symbols
+struct CharacterClasses(usize);
+
+impl PasswordRule for CharacterClasses {
+    fn check(&self, password: &str, _: &PasswordContext) -> Option<PolicyViolation> {
+        let classes = [
+            password.chars().any(char::is_lowercase),
+            password.chars().any(char::is_uppercase),
+            password.chars().any(char::is_numeric),
+            password
+                .chars()
+                .any(|c| !c.is_lowercase() && !c.is_uppercase() && !c.is_numeric()),
+        ];
+        let present = classes.iter().filter(|&&it| it).count();
+        (present < self.0).then_some(PolicyViolation::TooFewCharacterClasses { required: self.0 })
+    }
+}
+
+/// no username, parts of it, or personnel nr, ignoring case
+struct NoUserIdentity;
+
+impl PasswordRule for NoUserIdentity {
+    fn check(&self, password: &str, context: &PasswordContext) -> Option<PolicyViolation> {
+        let password = password.to_lowercase();
+        let username = context.user.username.to_lowercase();
+        let personnel_nr = context.user.personnel_nr.to_string();
+
+        let contains_identity = std::iter::once(username.as_str())
+            .chain(username.split_whitespace())
+            .chain(std::iter::once(personnel_nr.as_str()))
+            .filter(|identity| identity.chars().count() >= MIN_IDENTITY_LEN)
+            .any(|identity| password.contains(identity));
+        contains_identity.then_some(PolicyViolation::ContainsUserIdentity)
+    }
+}
+
+/// local list of breached or common passwords, one per line, compared ignoring case
+struct BreachedList(HashSet<String>);
+
+impl BreachedList {
+    fn load(path: &str) -> std::io::Result<Self> {
+        let passwords = std::fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        Ok(Self(passwords))
+    }
+}
+
+impl PasswordRule for BreachedList {
+    fn check(&self, password: &str, _: &PasswordContext) -> Option<PolicyViolation> {
+        self.0
+            .contains(&password.to_lowercase())
+            .then_some(PolicyViolation::Breached)
+    }
+}
+
+/// new password differs from the current one and the earlier ones kept in the history
+struct History {
+    hasher: PasswordHasher,
+    depth: usize,
+}
+
+impl PasswordRule for History {
+    fn check(&self, password: &str, context: &PasswordContext) -> Option<PolicyViolation> {
+        context
+            .previous
+            .iter()
+            .any(|previous| {
+                self.hasher.verify(
+                    &previous.salt,
+                    &previous.password,
+                    previous.iterations,
+                    password,
+                )
+            })
+            .then_some(PolicyViolation::Reused {
+                passwords: self.depth + 1,
+            })
+    }
+}
+
+/// Rules for new passwords
+#[derive(Clone)]
+pub struct PasswordPolicy {
+    rules: Vec<Arc<dyn PasswordRule>>,
+    /// new passwords expire after this period
+    lifetime: Duration,
+    /// forgot-password codes expire after this period
+    reset_code_lifetime: Duration,
+}
+
+impl PasswordPolicy {
+    pub fn new(config: &PasswordConfig, hasher: &PasswordHasher) -> Self {
+        let mut rules: Vec<Arc<dyn PasswordRule>> = vec![
+            Arc::new(MinLength(config.min_length)),
+            Arc::new(CharacterClasses(config.character_classes)),
+            Arc::new(NoUserIdentity),
+        ];
+        if !config.breached_list.is_empty() {
+            let list = BreachedList::load(&config.breached_list)
+                .expect("failed to load list of breached passwords");
+            log::info!("loaded {} breached passwords", list.0.len());
+            rules.push(Arc::new(list));
+        }
+        rules.push(Arc::new(History {
+            hasher: hasher.clone(),
+            depth: config.history_depth,
+        }));
+
+        Self {
+            rules,
+            lifetime: Duration::days(config.expiration_days),
+            reset_code_lifetime: Duration::minutes(config.reset_code_minutes),
+        }
+    }
+
+    /// all violations of `password`, with messages in `language`
+    pub fn check(
+        &self,
+        password: &str,
+        context: &PasswordContext,
+        language: Language,
+    ) -> Result<(), PasswordPolicyError> {
+        let violations: Vec<_> = self
+            .rules
+            .iter()
+            .filter_map(|rule| rule.check(password, context))
+            .collect();
+        if violations.is_empty() {
+            return Ok(());
+        }
+        Err(PasswordPolicyError {
+            violations,
+            language,
+        })
+    }
+
+    /// expiration date of a password changed on `today`; the last date there is
+    /// if the lifetime reaches beyond it
+    pub fn expiration_date(&self, today: NaiveDate) -> NaiveDate {
+        today
+            .checked_add_signed(self.lifetime)
+            .unwrap_or(NaiveDate::MAX)
+    }
+
+    /// expiration of a reset code issued at `now`
+    pub fn reset_code_expiration(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now + self.reset_code_lifetime
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::PasswordAlgorithm;
+
+    fn config() -> PasswordConfig {
+        PasswordConfig {
+            algorithm: PasswordAlgorithm::Pbkdf2,
+            pbkdf2_iterations: 1000,
+            ..PasswordConfig::default()
+        }
+    }
+
+    fn user() -> domain::User {
+        domain::User {
+            personnel_nr: 1234,
+            salt: String::new(),
+            password: String::new(),
+            password_iterations: 0,
+            password_expiration_date: NaiveDate::MAX,
+            username: "Ion Popescu".to_owned(),
+            account_disabled: false,
+            date_dismiss: None,
+            telefon: None,
+            email: None,
+        }
+    }
+
+    /// violations of `password` for `user()` without earlier passwords
+    fn violations(policy: &PasswordPolicy, password: &str) -> Vec<PolicyViolation> {
+        let user = user();
+        let context = PasswordContext {
+            user: &user,
+            previous: &[],
+        };
+        match policy.check(password, &context, Language::En) {
+            Ok(()) => Vec::new(),
+            Err(err) => err.violations,
+        }
+    }
+
+    fn policy(config: &PasswordConfig) -> PasswordPolicy {
+        PasswordPolicy::new(config, &PasswordHasher::new(config))
+    }
+
+    #[test]
+    fn strong_password_passes() {
+        assert_eq!(violations(&policy(&config()), "Cer-senin-42"), vec![]);
+    }
+
+    #[test]
+    fn short_password_is_rejected() {
+        assert_eq!(
+            violations(&policy(&config()), "Ab-1"),
+            vec![PolicyViolation::TooShort { min_length: 8 }]
+        );
+        // characters are counted, not bytes
+        assert_eq!(violations(&policy(&config()), "Șarpe-1ă"), vec![]);
+    }
+
+    #[test]
+    fn character_classes_are_counted() {
+        let policy = policy(&config());
+        assert_eq!(
+            violations(&policy, "cersenin"),
+            vec![PolicyViolation::TooFewCharacterClasses { required: 3 }]
+        );
+        assert_eq!(
+            violations(&policy, "Cersenin"),
+            vec![PolicyViolation::TooFewCharacterClasses { required: 3 }]
+        );
+        assert_eq!(violations(&policy, "Cersenin4"), vec![]);
+        assert_eq!(violations(&policy, "cer senin4"), vec![]);
+    }
+
+    #[test]
+    fn user_identity_is_rejected() {
+        let policy = policy(&config());
+        for password in ["Popescu-2024", "x-ION-y-9Z", "Nr-1234-abc", "ionpopescu-X1"] {
+            assert_eq!(
+                violations(&policy, password),
+                vec![PolicyViolation::ContainsUserIdentity],
+                "{}",
+                password
+            );
+        }
+        // ignored when shorter than MIN_IDENTITY_LEN
+        let mut user = user();
+        user.username = "Al".to_owned();
+        user.personnel_nr = 12;
+        let context = PasswordContext {
+            user: &user,
+            previous: &[],
+        };
+        assert!(policy
+            .check("Al-12-cersenin", &context, Language::En)
+            .is_ok());
+    }
+
+    #[test]
+    fn breached_passwords_are_rejected() {
+        let path = std::env::temp_dir().join(format!("breached-{}.txt", std::process::id()));
+        std::fs::write(&path, "Parola-123!\n\n  qwerty-ASD-1  \n").unwrap();
+        let config = PasswordConfig {
+            breached_list: path.to_string_lossy().into_owned(),
+            ..config()
+        };
+        let policy = policy(&config);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            violations(&policy, "parola-123!"),
+            vec![PolicyViolation::Breached]
+        );
+        assert_eq!(
+            violations(&policy, "QWERTY-asd-1"),
+            vec![PolicyViolation::Breached]
+        );
+        assert_eq!(violations(&policy, "Cer-senin-42"), vec![]);
+    }
+
+    #[test]
+    fn previous_passwords_are_rejected() {
+        let config = config();
+        let hasher = PasswordHasher::new(&config);
+        let policy = PasswordPolicy::new(&config, &hasher);
+        let previous = [hasher.hash("Cer-senin-42"), hasher.hash("Munte-verde-7")];
+        let user = user();
+        let context = PasswordContext {
+            user: &user,
+            previous: &previous,
+        };
+
+        let err = policy
+            .check("Munte-verde-7", &context, Language::En)
+            .unwrap_err();
+        assert_eq!(
+            err.violations,
+            vec![PolicyViolation::Reused { passwords: 6 }]
+        );
+        assert!(policy
+            .check("Mare-albastra-3", &context, Language::En)
+            .is_ok());
+    }
+
+    #[test]
+    fn all_violations_are_reported() {
+        assert_eq!(
+            violations(&policy(&config()), "ion"),
+            vec![
+                PolicyViolation::TooShort { min_length: 8 },
+                PolicyViolation::TooFewCharacterClasses { required: 3 },
+                PolicyViolation::ContainsUserIdentity,
+            ]
+        );
+    }
+}
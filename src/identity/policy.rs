@@ -0,0 +1,112 @@
+use chrono::{DateTime, Duration, Utc};
+use derive_more::Display;
+
+use super::AuthenticatedUser;
+use crate::setup::SessionConfig;
+
+/// Session lifetime rules, see `setup::SessionConfig`
+#[derive(Clone, Copy)]
+pub struct SessionPolicy {
+    max_lifetime: Duration,
+    idle_timeout: Option<Duration>,
+    sliding: bool,
+    max_sessions: Option<usize>,
+    access_token_lifetime: Option<Duration>,
+}
+
+/// The limit a session has exceeded
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionLimit {
+    #[display(fmt = "Session expired: maximal session lifetime exceeded")]
+    Lifetime,
+    #[display(fmt = "Session expired: idle timeout exceeded")]
+    Idle,
+    #[display(fmt = "Access token expired; use refresh token")]
+    AccessToken,
+}
+
+/// Sessions authenticated or last seen before these moments are expired
+#[derive(Clone, Copy)]
+pub struct Expiration {
+    pub authenticated_before: DateTime<Utc>,
+    pub last_seen_before: Option<DateTime<Utc>>,
+}
+
+impl SessionPolicy {
+    pub fn new(config: &SessionConfig) -> Self {
+        let idle_timeout =
+            Some(Duration::minutes(config.idle_timeout_minutes)).filter(|d| *d > Duration::zero());
+        Self {
+            max_lifetime: Duration::minutes(config.max_lifetime_minutes),
+            idle_timeout,
+            sliding: config.sliding_renewal,
+            max_sessions: Some(config.max_sessions_per_user).filter(|max| *max > 0),
+            access_token_lifetime: Some(Duration::minutes(config.access_token_minutes))
+                .filter(|d| *d > Duration::zero()),
+        }
+    }
+
+    /// maximal count of concurrent sessions of one user
+    pub fn max_sessions(&self) -> Option<usize> {
+        self.max_sessions
+    }
+
+    pub fn expiration(&self, now: DateTime<Utc>) -> Expiration {
+        Expiration {
+            authenticated_before: now - self.max_lifetime,
+            last_seen_before: self.idle_timeout.map(|idle| now - idle),
+        }
+    }
+
+    /// the auth token of a session lives shorter than the session itself
+    /// and is renewed with refresh token
+    pub fn access_token_expired(&self, auth_user: &AuthenticatedUser, now: DateTime<Utc>) -> bool {
+        self.access_token_expires_in(auth_user, now) <= Duration::zero()
+    }
+
+    /// time until the auth token of session expires
+    pub fn access_token_expires_in(
+        &self,
+        auth_user: &AuthenticatedUser,
+        now: DateTime<Utc>,
+    ) -> Duration {
+        let session_expires = *auth_user.authenticated.read().unwrap() + self.max_lifetime;
+        let expires = match self.access_token_lifetime {
+            Some(lifetime) => {
+                let token_expires = *auth_user.token_issued.read().unwrap() + lifetime;
+                token_expires.min(session_expires)
+            }
+            None => session_expires,
+        };
+        expires - now
+    }
+
+    /// new auth timestamp of an active session, if it must be renewed;
+    /// with sliding renewal the session lifetime is extended
+    /// once half of it has passed
+    pub fn renewal(
+        &self,
+        authenticated: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        if self.sliding && now - authenticated > self.max_lifetime / 2 {
+            Some(now)
+        } else {
+            None
+        }
+    }
+}
+
+impl Expiration {
+    pub fn exceeded_limit(&self, auth_user: &AuthenticatedUser) -> Option<SessionLimit> {
+        if *auth_user.authenticated.read().unwrap() < self.authenticated_before {
+            return Some(SessionLimit::Lifetime);
+        }
+        match self.last_seen_before {
+            Some(last_seen_before) if *auth_user.last_seen.read().unwrap() < last_seen_before => {
+                Some(SessionLimit::Idle)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::future::{ready, Future, Ready};
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::http::Method;
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpMessage};
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+
+use crate::errors::RateLimitedError;
+use crate::setup::RateLimitConfig;
+
+/// buckets kept per kind of key; beyond, full and then least recently used ones are dropped
+const MAX_BUCKETS: usize = 10_000;
+/// buckets left after dropping, so that the next sweep is far off
+const KEEP_BUCKETS: usize = MAX_BUCKETS * 9 / 10;
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// State of a bucket after a request, for the `RateLimit-*` headers
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    /// burst size
+    pub limit: u32,
+    pub remaining: u32,
+    /// seconds until the bucket is full again
+    pub reset_secs: u64,
+    /// seconds until the next request is allowed, if this one was rejected
+    pub retry_after_secs: Option<u64>,
+}
+
+impl Quota {
+    fn rejected(&self) -> bool {
+        self.retry_after_secs.is_some()
+    }
+
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(RATELIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(RATELIMIT_REMAINING, HeaderValue::from(self.remaining));
+        headers.insert(RATELIMIT_RESET, HeaderValue::from(self.reset_secs));
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets of one kind of key: `burst` requests at once,
+/// refilled with `per_minute` requests
+struct Buckets<K> {
+    burst: f64,
+    per_sec: f64,
+    buckets: Mutex<HashMap<K, Bucket>>,
+}
+
+impl<K: Eq + Hash> Buckets<K> {
+    fn new(burst: u32, per_minute: u32) -> Option<Self> {
+        if per_minute == 0 {
+            return None;
+        }
+        Some(Self {
+            burst: burst.max(1) as f64,
+            per_sec: per_minute as f64 / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_sec).min(self.burst)
+    }
+
+    /// shrinks `buckets` to `KEEP_BUCKETS` at most
+    fn evict(&self, buckets: &mut HashMap<K, Bucket>, now: Instant) {
+        // full buckets hold nothing a new bucket wouldn't
+        buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+        if buckets.len() <= KEEP_BUCKETS {
+            return;
+        }
+        let mut updated: Vec<Instant> = buckets.values().map(|bucket| bucket.updated).collect();
+        let (_, cutoff, _) = updated.select_nth_unstable(buckets.len() - KEEP_BUCKETS - 1);
+        let cutoff = *cutoff;
+        buckets.retain(|_, bucket| bucket.updated > cutoff);
+    }
+
+    /// takes a token for `key`, if there is one
+    fn take(&self, key: K, now: Instant) -> Quota {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&key) {
+            self.evict(&mut buckets, now);
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let mut tokens = self.refilled(bucket, now);
+        let allowed = tokens >= 1.0;
+        if allowed {
+            tokens -= 1.0;
+        }
+        bucket.tokens = tokens;
+        bucket.updated = now;
+
+        Quota {
+            limit: self.burst as u32,
+            remaining: tokens as u32,
+            reset_secs: ((self.burst - tokens) / self.per_sec).ceil() as u64,
+            retry_after_secs: (!allowed).then(|| ((1.0 - tokens) / self.per_sec).ceil() as u64),
+        }
+    }
+}
+
+/// IP network in CIDR notation; a plain IP is a network of one address
+struct IpNetwork {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl IpNetwork {
+    fn parse(value: &str) -> Option<Self> {
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr.parse().ok()?, Some(prefix.parse().ok()?)),
+            None => (value.parse().ok()?, None),
+        };
+        let bits = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = prefix.unwrap_or(bits);
+        (prefix <= bits).then_some(Self { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Shared by the workers of the server
+struct Limits {
+    by_ip: Option<Buckets<IpAddr>>,
+    /// keyed by personnel nr, as the handlers parse the username
+    by_account: Option<Buckets<i16>>,
+    allowlist: Vec<IpNetwork>,
+}
+
+impl Limits {
+    /// the most restrictive quota of the request; `Err` when it is over a limit
+    fn check(&self, ip: Option<IpAddr>, personnel_nr: Option<i16>) -> Result<Option<Quota>, Quota> {
+        let now = Instant::now();
+        let ip = ip.filter(|ip| !self.allowlist.iter().any(|network| network.contains(*ip)));
+
+        let by_ip = match (&self.by_ip, ip) {
+            (Some(buckets), Some(ip)) => Some(buckets.take(ip, now)),
+            _ => None,
+        };
+        if let Some(rejected) = by_ip.filter(Quota::rejected) {
+            return Err(rejected);
+        }
+        let by_account = match (&self.by_account, personnel_nr) {
+            (Some(buckets), Some(personnel_nr)) => Some(buckets.take(personnel_nr, now)),
+            _ => None,
+        };
+        if let Some(rejected) = by_account.filter(Quota::rejected) {
+            return Err(rejected);
+        }
+
+        Ok(by_ip
+            .into_iter()
+            .chain(by_account)
+            .min_by_key(|quota| quota.remaining))
+    }
+}
+
+/// username of login and password forms, in JSON or urlencoded
+#[derive(Deserialize)]
+struct UsernameField {
+    username: Option<String>,
+}
+
+/// personnel nr of the username; other usernames can't log in and aren't counted
+fn personnel_nr_of(content_type: &str, body: &[u8]) -> Option<i16> {
+    let field: UsernameField = match content_type {
+        "application/json" => serde_json::from_slice(body).ok()?,
+        "application/x-www-form-urlencoded" => serde_urlencoded::from_bytes(body).ok()?,
+        _ => return None,
+    };
+    field.username?.parse().ok()
+}
+
+/// request body, for the handler after the middleware has read it
+fn payload_of(body: Bytes) -> Payload {
+    Payload::from(stream::once(async move { Ok(body) }).boxed_local())
+}
+
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<S>,
+    paths: Arc<Vec<&'static str>>,
+    limits: Arc<Limits>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        if req.method() != Method::POST || !self.paths.contains(&req.path()) {
+            return Box::pin(service.call(req));
+        }
+
+        let limits = self.limits.clone();
+        Box::pin(async move {
+            let personnel_nr = if limits.by_account.is_some() {
+                let body = req.extract::<Bytes>().await?;
+                let personnel_nr = personnel_nr_of(req.content_type(), &body);
+                req.set_payload(payload_of(body));
+                personnel_nr
+            } else {
+                None
+            };
+            let ip = req.peer_addr().map(|addr| addr.ip());
+
+            let quota = limits
+                .check(ip, personnel_nr)
+                .map_err(|quota| RateLimitedError { quota })?;
+
+            let mut res = service.call(req).await?;
+            if let Some(quota) = quota {
+                quota.insert_headers(res.headers_mut());
+            }
+            Ok(res)
+        })
+    }
+}
+
+/// Throttles POST requests to the given paths with token buckets per client IP
+/// and per username; rejected requests get `429 Too Many Requests`
+#[derive(Clone)]
+pub struct RateLimiter {
+    paths: Arc<Vec<&'static str>>,
+    limits: Arc<Limits>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        let allowlist = config
+            .allowlist
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                IpNetwork::parse(value)
+                    .unwrap_or_else(|| panic!("invalid rate limit allowlist entry: {}", value))
+            })
+            .collect();
+        Self {
+            paths: Arc::new(Vec::new()),
+            limits: Arc::new(Limits {
+                by_ip: Buckets::new(config.ip_burst, config.ip_per_minute),
+                by_account: Buckets::new(config.account_burst, config.account_per_minute),
+                allowlist,
+            }),
+        }
+    }
+
+    /// throttle requests to `path` as well
+    pub fn limit(mut self, path: &'static str) -> Self {
+        Arc::make_mut(&mut self.paths).push(path);
+        self
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimiterMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimiterMiddleware {
+            service: Rc::new(service),
+            paths: self.paths.clone(),
+            limits: self.limits.clone(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn network(value: &str) -> IpNetwork {
+        IpNetwork::parse(value).unwrap()
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn plain_ip_is_network_of_one() {
+        let v4 = network("10.1.2.3");
+        assert!(v4.contains(ip("10.1.2.3")));
+        assert!(!v4.contains(ip("10.1.2.4")));
+
+        let v6 = network("2001:db8::1");
+        assert!(v6.contains(ip("2001:db8::1")));
+        assert!(!v6.contains(ip("2001:db8::2")));
+    }
+
+    #[test]
+    fn cidr_prefixes() {
+        let v4 = network("192.168.0.0/16");
+        assert!(v4.contains(ip("192.168.255.1")));
+        assert!(!v4.contains(ip("192.169.0.1")));
+
+        let host = network("192.168.1.7/32");
+        assert!(host.contains(ip("192.168.1.7")));
+        assert!(!host.contains(ip("192.168.1.6")));
+
+        let v6 = network("2001:db8::/32");
+        assert!(v6.contains(ip("2001:db8:ffff::1")));
+        assert!(!v6.contains(ip("2001:db9::1")));
+
+        let host = network("::1/128");
+        assert!(host.contains(ip("::1")));
+        assert!(!host.contains(ip("::2")));
+    }
+
+    #[test]
+    fn zero_prefix_contains_its_whole_family() {
+        let v4 = network("0.0.0.0/0");
+        assert!(v4.contains(ip("1.2.3.4")));
+        assert!(v4.contains(ip("255.255.255.255")));
+        assert!(!v4.contains(ip("::1")));
+
+        let v6 = network("::/0");
+        assert!(v6.contains(ip("2001:db8::1")));
+        assert!(!v6.contains(ip("1.2.3.4")));
+    }
+
+    #[test]
+    fn families_dont_mix() {
+        // IPv4-mapped IPv6 addresses are not IPv4 addresses
+        assert!(!network("10.0.0.0/8").contains(ip("::ffff:10.0.0.1")));
+        assert!(!network("::ffff:0:0/96").contains(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn invalid_networks() {
+        for value in [
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/",
+            "10.0.0.0/-1",
+            "10.0.0/8",
+            "localhost",
+            "",
+        ] {
+            assert!(IpNetwork::parse(value).is_none(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn bucket_allows_burst_then_refills() {
+        let buckets = Buckets::new(3, 60).unwrap();
+        let now = Instant::now();
+
+        for remaining in [2, 1, 0] {
+            let quota = buckets.take(1, now);
+            assert!(!quota.rejected());
+            assert_eq!(quota.remaining, remaining);
+        }
+        let quota = buckets.take(1, now);
+        assert_eq!(quota.retry_after_secs, Some(1));
+        assert_eq!(quota.reset_secs, 3);
+
+        // other keys have their own bucket
+        assert!(!buckets.take(2, now).rejected());
+
+        // one token per second
+        assert!(!buckets.take(1, now + Duration::from_secs(1)).rejected());
+        assert!(buckets.take(1, now + Duration::from_secs(1)).rejected());
+    }
+
+    #[test]
+    fn disabled_without_refill() {
+        assert!(Buckets::<i16>::new(5, 0).is_none());
+    }
+
+    #[test]
+    fn full_buckets_are_dropped_first() {
+        let buckets = Buckets::new(1, 60).unwrap();
+        let now = Instant::now();
+        for key in 0..MAX_BUCKETS as u32 {
+            buckets.take(key, now);
+        }
+
+        // refilled by now, so all of them
+        buckets.take(u32::MAX, now + Duration::from_secs(1));
+        assert_eq!(buckets.buckets.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn least_recently_used_buckets_are_dropped() {
+        let buckets = Buckets::new(10, 1).unwrap();
+        let start = Instant::now();
+        for key in 0..MAX_BUCKETS as u32 {
+            buckets.take(key, start + Duration::from_millis(key as u64));
+        }
+        let now = start + Duration::from_millis(MAX_BUCKETS as u64);
+
+        // known keys don't evict
+        buckets.take(0, now);
+        assert_eq!(buckets.buckets.lock().unwrap().len(), MAX_BUCKETS);
+
+        buckets.take(u32::MAX, now);
+        let map = buckets.buckets.lock().unwrap();
+        assert_eq!(map.len(), KEEP_BUCKETS + 1);
+        assert!(map.contains_key(&0));
+        assert!(!map.contains_key(&1));
+        assert!(map.contains_key(&(MAX_BUCKETS as u32 - 1)));
+        assert!(map.contains_key(&u32::MAX));
+    }
+
+    #[test]
+    fn account_key_is_personnel_nr() {
+        let json = "application/json";
+        assert_eq!(personnel_nr_of(json, br#"{"username":"7"}"#), Some(7));
+        assert_eq!(personnel_nr_of(json, br#"{"username":"07"}"#), Some(7));
+        assert_eq!(personnel_nr_of(json, br#"{"username":"+7"}"#), Some(7));
+        assert_eq!(personnel_nr_of(json, br#"{"username":"ion"}"#), None);
+        assert_eq!(personnel_nr_of(json, br#"{"username":"99999"}"#), None);
+        assert_eq!(personnel_nr_of(json, br#"{"password":"x"}"#), None);
+
+        let form = "application/x-www-form-urlencoded";
+        assert_eq!(personnel_nr_of(form, b"username=%2B7&password=x"), Some(7));
+        assert_eq!(personnel_nr_of("text/plain", b"username=7"), None);
+    }
+}
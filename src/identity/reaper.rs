@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+use actix_web::rt;
+
+use super::Identity;
+
+/// periodically removes expired sessions from the session store
+pub fn spawn_session_reaper(identity: Identity, period: Duration) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(period);
+        loop {
+            interval.tick().await;
+            match identity.evict_expired_sessions().await {
+                Ok(0) => {}
+                Ok(removed) => log::info!("removed {} expired sessions", removed),
+                Err(err) => log::error!("failed to remove expired sessions: {}", err),
+            }
+        }
+    });
+}
@@ -1,110 +1,283 @@
 use crate::domain;
 
-use chrono::Utc;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex, RwLock};
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
 use uuid::Uuid;
 
-use base64::{decode, encode};
-use ring::{digest, pbkdf2};
-use std::num::NonZeroU32;
-
-use super::{AuthenticatedUser, AuthenticationResponse};
+use super::{
+    token, AuthenticatedUser, AuthenticationResponse, HashedPassword, JwtIssuer, LockoutPolicy,
+    PasswordContext, PasswordHasher, PasswordPolicy, RemovedSession, Rotation, SessionDevice,
+    SessionLimit, SessionPolicy, SessionStore, SessionSummary, TokenDigest, TokenInfo, TokenType,
+};
+use crate::errors::{OAuthError, PasswordPolicyError};
+use crate::locale::Language;
 
 #[derive(Clone)]
 pub struct Identity {
-    iterations: NonZeroU32,
-    users_by_uuid: Arc<RwLock<HashMap<Uuid, Arc<AuthenticatedUser>>>>,
-    users_by_personnel_nr: Arc<Mutex<HashMap<i16, AuthenticationResponse>>>,
+    sessions: Arc<dyn SessionStore>,
+    policy: SessionPolicy,
+    jwt: Option<Arc<JwtIssuer>>,
+    admin_role: Arc<String>,
+    password_policy: PasswordPolicy,
+    hasher: PasswordHasher,
+    lockout: LockoutPolicy,
 }
 
-static PBKDF2_ALG: pbkdf2::Algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
-
-const CREDENTIAL_LEN: usize = digest::SHA256_OUTPUT_LEN;
-type Credential = [u8; CREDENTIAL_LEN];
+/// PBKDF2 cost of oauth client secrets, which are registered outside of this server
+const CLIENT_SECRET_ITERATIONS: i32 = 1000;
 
 impl Identity {
-    pub fn new() -> Identity {
+    pub fn new(
+        sessions: Arc<dyn SessionStore>,
+        policy: SessionPolicy,
+        jwt: Option<JwtIssuer>,
+        admin_role: String,
+        password_policy: PasswordPolicy,
+        hasher: PasswordHasher,
+        lockout: LockoutPolicy,
+    ) -> Identity {
         Identity {
-            iterations: NonZeroU32::new(1000).unwrap(),
-            users_by_uuid: Arc::new(RwLock::new(HashMap::new())),
-            users_by_personnel_nr: Arc::new(Mutex::new(HashMap::new())),
+            sessions,
+            policy,
+            jwt: jwt.map(Arc::new),
+            admin_role: Arc::new(admin_role),
+            password_policy,
+            hasher,
+            lockout,
         }
     }
 
-    pub fn authenticate(
+    /// user may manage tokens and accounts of other users
+    pub fn is_admin(&self, auth_info: &AuthenticatedUser) -> bool {
+        auth_info
+            .roles()
+            .iter()
+            .any(|role| role.role_name == *self.admin_role)
+    }
+
+    pub async fn authenticate(
         &self,
         user: domain::User,
         roles: Vec<domain::UserRole>,
         resources: Vec<domain::UserResource>,
+        device: SessionDevice,
+        restricted: bool,
+        issued_to: Option<&str>,
     ) -> Result<AuthenticationResponse, actix_web::Error> {
-        // guard hashmap for write
-        let mut guard = self.users_by_personnel_nr.lock().unwrap();
-        if let Some(auth_response) = guard.get_mut(&user.personnel_nr) {
-            // if auth record exists, renew auth timestamp
-            // TODO: may be renew roles ??
-            {
-                let mut guard = auth_response.auth_info.authenticated.write().unwrap();
-                *guard = Utc::now();
-            }
-            return Ok(auth_response.clone());
+        // every login opens a new session
+        let now = Utc::now();
+        let mut auth_info = AuthenticatedUser::new(user, roles, resources, device, now);
+        if restricted {
+            auth_info = auth_info.into_restricted();
         }
+        if let Some(client_id) = issued_to {
+            auth_info = auth_info.into_issued_to(client_id);
+        }
+        let auth_info = Arc::new(auth_info);
+        let response = self.open_session(auth_info.clone(), now).await?;
+
+        // fresh permissions reach the other sessions of the user as well
+        self.sessions
+            .set_permissions(
+                auth_info.user.personnel_nr,
+                auth_info.roles(),
+                auth_info.resources(),
+            )
+            .await?;
+        Ok(response)
+    }
 
-        // user not exists in hashmaps
-        // generate new auth info
+    /// replace roles and resources of the session of `auth_info`
+    /// and of all other sessions of the same user
+    pub async fn refresh_permissions(
+        &self,
+        auth_info: &AuthenticatedUser,
+        roles: Vec<domain::UserRole>,
+        resources: Vec<domain::UserResource>,
+    ) -> Result<(), actix_web::Error> {
+        let roles = Arc::new(roles);
+        let resources = Arc::new(resources);
+        auth_info.set_permissions(roles.clone(), resources.clone());
+
+        if !auth_info.is_service() {
+            let updated = self
+                .sessions
+                .set_permissions(auth_info.user.personnel_nr, roles, resources)
+                .await?;
+            log::info!(
+                "reloaded permissions of user {} in {} sessions",
+                auth_info.user.personnel_nr,
+                updated
+            );
+        }
+        Ok(())
+    }
 
-        let key = user.personnel_nr;
-        let auth_info = Arc::new(AuthenticatedUser {
-            user,
-            roles: Arc::new(roles),
-            resources: Arc::new(resources),
-            authenticated: RwLock::new(Utc::now()),
-        });
+    /// client credentials grant: session of a service client
+    pub async fn authenticate_service(
+        &self,
+        client: &domain::OAuthClient,
+        roles: Vec<domain::UserRole>,
+        resources: Vec<domain::UserResource>,
+        device: SessionDevice,
+    ) -> Result<AuthenticationResponse, actix_web::Error> {
+        let now = Utc::now();
+        let auth_info = Arc::new(AuthenticatedUser::service(
+            client, roles, resources, device, now,
+        ));
+        self.open_session(auth_info, now).await
+    }
 
-        let token = Uuid::new_v4();
+    /// service clients get neither refresh token nor a limit of sessions;
+    /// restricted sessions get neither refresh token nor JWT
+    async fn open_session(
+        &self,
+        auth_info: Arc<AuthenticatedUser>,
+        now: DateTime<Utc>,
+    ) -> Result<AuthenticationResponse, actix_web::Error> {
+        let access_token = match &self.jwt {
+            Some(jwt) if !auth_info.restricted => Some(jwt.issue(
+                &auth_info,
+                now,
+                self.policy.access_token_expires_in(&auth_info, now),
+            )?),
+            _ => None,
+        };
+        let (token, digest) = token::generate(TokenType::AccessToken);
+        let refresh_token = if auth_info.is_service() || auth_info.restricted {
+            None
+        } else {
+            Some(token::generate(TokenType::RefreshToken))
+        };
+        let max_sessions = if auth_info.is_service() {
+            None
+        } else {
+            self.policy.max_sessions()
+        };
 
-        let auth_response = AuthenticationResponse {
-            auth_info: auth_info.clone(),
+        let evicted = self
+            .sessions
+            .insert(
+                digest,
+                refresh_token.as_ref().map(|(_, digest)| *digest),
+                auth_info.clone(),
+                max_sessions,
+            )
+            .await?;
+        if evicted > 0 {
+            log::info!(
+                "closed {} oldest sessions of user {}",
+                evicted,
+                auth_info.user.personnel_nr
+            );
+        }
+
+        Ok(AuthenticationResponse {
             token,
+            expires_in: self
+                .policy
+                .access_token_expires_in(&auth_info, now)
+                .num_seconds(),
+            refresh_token: refresh_token.map(|(refresh_token, _)| refresh_token),
+            access_token,
+            password_expires_in_days: password_expires_in_days(&auth_info),
+            must_change_password: auth_info.restricted,
+            auth_info,
+        })
+    }
+
+    /// exchange refresh token for new auth and refresh tokens of the same session;
+    /// `client_id` is the oauth client redeeming the token, if any
+    pub async fn refresh(
+        &self,
+        refresh_token: &str,
+        client_id: Option<&str>,
+    ) -> Result<AuthenticationResponse, actix_web::Error> {
+        let refresh_token = match token::parse(refresh_token) {
+            Some((TokenType::RefreshToken, digest)) => digest,
+            _ => return Err(actix_web::error::ErrorBadRequest("invalid refresh token")),
         };
 
-        // insert into hashmap by personnel nr
-        guard.insert(key, auth_response.clone());
+        let now = Utc::now();
+        let (token, digest) = token::generate(TokenType::AccessToken);
+        let (new_refresh_token, new_refresh_digest) = token::generate(TokenType::RefreshToken);
+
+        let rotation = self
+            .sessions
+            .rotate(refresh_token, digest, new_refresh_digest, client_id, now)
+            .await?;
+
+        let auth_info = match rotation {
+            Rotation::Rotated(auth_info) => auth_info,
+            Rotation::Reused(personnel_nr) => {
+                log::warn!(
+                    "reused refresh token of user {}; session revoked",
+                    personnel_nr
+                );
+                return Err(actix_web::error::ErrorUnauthorized(
+                    "Refresh token already used; session revoked",
+                ));
+            }
+            Rotation::WrongClient => {
+                return Err(
+                    OAuthError::InvalidGrant("refresh token was issued to another client").into(),
+                )
+            }
+            Rotation::Unknown => {
+                return Err(actix_web::error::ErrorUnauthorized(
+                    "You are not authenticated; invalid refresh token",
+                ))
+            }
+        };
 
-        // insert into hashmap by uuid token
-        let mut guard = self.users_by_uuid.write().unwrap();
-        guard.insert(token, auth_info);
+        // refresh token can't outlive the session
+        if let Some(limit) = self.policy.expiration(now).exceeded_limit(&auth_info) {
+            self.sessions.remove(digest).await?;
+            return Err(actix_web::error::ErrorUnauthorized(limit.to_string()));
+        }
+
+        let access_token = match &self.jwt {
+            Some(jwt) => Some(jwt.issue(
+                &auth_info,
+                now,
+                self.policy.access_token_expires_in(&auth_info, now),
+            )?),
+            None => None,
+        };
 
-        Ok(auth_response)
+        Ok(AuthenticationResponse {
+            token,
+            expires_in: self
+                .policy
+                .access_token_expires_in(&auth_info, now)
+                .num_seconds(),
+            refresh_token: Some(new_refresh_token),
+            access_token,
+            password_expires_in_days: password_expires_in_days(&auth_info),
+            must_change_password: auth_info.restricted,
+            auth_info,
+        })
     }
 
-    pub fn authorization_info(
+    pub async fn authorization_info(
         &self,
         token: &str,
     ) -> Result<Arc<AuthenticatedUser>, actix_web::Error> {
-        let key = Uuid::parse_str(token)
-            .map_err(|_| actix_web::error::ErrorBadRequest("invalid auth token"))?;
+        let key = parse_token(token)?;
 
-        let guard = self.users_by_uuid.read().unwrap();
-
-        let info = guard.get(&key).map(|it| it.clone());
+        let info = self.sessions.get(key).await?;
 
         match info {
             Some(info) => {
-                // TODO: check duration; maximal session time must be 12Hours
-                {
-                    let hours = -{
-                        let now = Utc::now();
-                        let authenticated = info.authenticated.read().unwrap();
-                        let duration = authenticated.signed_duration_since(now);
-                        duration.num_hours()
-                    };
-                    if hours > 12 {
-                        // session is outdated
-                        Err(actix_web::error::ErrorUnauthorized("Session expired"))
-                    } else {
-                        Ok(info)
-                    }
+                let now = Utc::now();
+                let expiration = self.policy.expiration(now);
+                match expiration.exceeded_limit(&info) {
+                    // session is outdated
+                    Some(limit) => Err(actix_web::error::ErrorUnauthorized(limit.to_string())),
+                    None if self.policy.access_token_expired(&info, now) => Err(
+                        actix_web::error::ErrorUnauthorized(SessionLimit::AccessToken.to_string()),
+                    ),
+                    None => Ok(info),
                 }
             }
             None => Err(actix_web::error::ErrorUnauthorized(
@@ -113,89 +286,424 @@ impl Identity {
         }
     }
 
-    pub fn logout(&self, token: &str) -> Result<(), actix_web::Error> {
-        let key = Uuid::parse_str(token)
-            .map_err(|_| actix_web::error::ErrorBadRequest("invalid auth token"))?;
+    /// state of auth token for resource servers;
+    /// `None` if token is unknown or expired, or its session is restricted
+    pub async fn introspect(&self, token: &str) -> Result<Option<TokenInfo>, actix_web::Error> {
+        let auth_info = match self.authorization_info(token).await {
+            Ok(auth_info) if auth_info.restricted => return Ok(None),
+            Ok(auth_info) => auth_info,
+            Err(err) if err.as_response_error().status_code().is_client_error() => return Ok(None),
+            Err(err) => return Err(err),
+        };
 
-        let mut guard = self.users_by_uuid.write().unwrap();
+        let now = Utc::now();
+        let issued_at = *auth_info.token_issued.read().unwrap();
+        let expires_at = now + self.policy.access_token_expires_in(&auth_info, now);
+        Ok(Some(TokenInfo {
+            auth_info,
+            issued_at,
+            expires_at,
+        }))
+    }
 
-        let auth_user = guard.remove(&key);
-        if let Some(auth_user) = auth_user {
-            let personnel_nr = auth_user.user.personnel_nr;
-            let mut guard = self.users_by_personnel_nr.lock().unwrap();
-            guard.remove(&personnel_nr).unwrap();
+    /// check secret of registered oauth client
+    pub fn verify_client_secret(
+        &self,
+        client: &domain::OAuthClient,
+        attempted_secret: &str,
+    ) -> Result<(), actix_web::Error> {
+        if client.disabled {
+            return Err(OAuthError::InvalidClient.into());
+        }
+        if !self.hasher.verify(
+            &client.salt,
+            &client.secret,
+            CLIENT_SECRET_ITERATIONS,
+            attempted_secret,
+        ) {
+            return Err(OAuthError::InvalidClient.into());
         }
-
         Ok(())
     }
 
-    pub fn verify_authentication(
+    pub fn jwt(&self) -> Result<&JwtIssuer, actix_web::Error> {
+        self.jwt
+            .as_deref()
+            .ok_or_else(|| actix_web::error::ErrorNotFound("JWT access tokens are not enabled"))
+    }
+
+    /// public keys for verification of JWT access tokens
+    pub fn jwks(&self) -> Result<serde_json::Value, actix_web::Error> {
+        self.jwt()?
+            .jwks()
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    /// OpenID Connect id token of the session for the oauth client `client_id`
+    pub fn id_token(
         &self,
-        user: &domain::User,
-        attempted_password: &str,
+        auth_info: &AuthenticatedUser,
+        client_id: &str,
+        nonce: Option<&str>,
+    ) -> Result<String, actix_web::Error> {
+        self.jwt()?
+            .issue_id_token(auth_info, client_id, nonce, Utc::now())
+    }
+
+    /// active sessions of the user, `current` marks the session of `auth_info`
+    pub async fn sessions_of(
+        &self,
+        auth_info: &AuthenticatedUser,
+    ) -> Result<Vec<SessionSummary>, actix_web::Error> {
+        let personnel_nr = session_owner(auth_info)?;
+        let expiration = self.policy.expiration(Utc::now());
+        let mut sessions = self.sessions.sessions_of(personnel_nr, expiration).await?;
+        for session in sessions.iter_mut() {
+            session.current = session.id == auth_info.id;
+        }
+        Ok(sessions)
+    }
+
+    /// close one of own sessions; returns false if there is no such session
+    pub async fn revoke_session(
+        &self,
+        auth_info: &AuthenticatedUser,
+        id: Uuid,
+    ) -> Result<bool, actix_web::Error> {
+        let personnel_nr = session_owner(auth_info)?;
+        let removed = self.sessions.remove_by_id(personnel_nr, id).await?;
+        Ok(removed)
+    }
+
+    /// close all own sessions except the one of `auth_info`;
+    /// returns count of closed sessions
+    pub async fn revoke_other_sessions(
+        &self,
+        auth_info: &AuthenticatedUser,
+    ) -> Result<usize, actix_web::Error> {
+        let personnel_nr = session_owner(auth_info)?;
+        let removed = self
+            .sessions
+            .remove_others(personnel_nr, auth_info.id)
+            .await?;
+        Ok(removed)
+    }
+
+    /// close all sessions of the user, e.g. when the account was disabled;
+    /// returns count of closed sessions
+    pub async fn revoke_sessions_of_user(
+        &self,
+        personnel_nr: i16,
+    ) -> Result<usize, actix_web::Error> {
+        let removed = self.sessions.remove_all(personnel_nr).await?;
+        Ok(removed)
+    }
+
+    /// users with live sessions
+    pub async fn session_owners(&self) -> Result<Vec<i16>, actix_web::Error> {
+        Ok(self.sessions.owners().await?)
+    }
+
+    /// replace roles and resources in all live sessions of the user;
+    /// returns count of updated sessions
+    pub async fn reload_permissions_of_user(
+        &self,
+        personnel_nr: i16,
+        roles: Vec<domain::UserRole>,
+        resources: Vec<domain::UserResource>,
+    ) -> Result<usize, actix_web::Error> {
+        let updated = self
+            .sessions
+            .set_permissions(personnel_nr, Arc::new(roles), Arc::new(resources))
+            .await?;
+        Ok(updated)
+    }
+
+    /// update last activity of session;
+    /// with sliding renewal also extends the session lifetime
+    pub async fn record_activity(
+        &self,
+        token: &str,
+        auth_info: &AuthenticatedUser,
     ) -> Result<(), actix_web::Error> {
-        self.verify_password(&user.salt, &user.password, attempted_password)?;
+        let key = parse_token(token)?;
 
-        let hours = {
-            let now = Utc::now().date_naive();
-            let expiration_date = user.password_expiration_date;
-            let duration = expiration_date.signed_duration_since(now);
-            duration.num_hours()
+        let now = Utc::now();
+        let authenticated = *auth_info.authenticated.read().unwrap();
+        let renewed = self.policy.renewal(authenticated, now);
+
+        self.sessions.touch(key, now, renewed).await?;
+        Ok(())
+    }
+
+    /// remove all expired sessions;
+    /// returns count of removed sessions
+    pub async fn evict_expired_sessions(&self) -> Result<usize, actix_web::Error> {
+        let expiration = self.policy.expiration(Utc::now());
+        let removed = self.sessions.evict_expired(expiration).await?;
+        Ok(removed)
+    }
+
+    pub async fn logout(&self, token: &str) -> Result<Option<RemovedSession>, actix_web::Error> {
+        let key = parse_token(token)?;
+
+        let removed = self.sessions.remove(key).await?;
+
+        Ok(removed)
+    }
+
+    /// revoke any access or refresh token, closing its session;
+    /// the token prefix tells which kind of token it is
+    pub async fn revoke(
+        &self,
+        token: &str,
+    ) -> Result<Option<(RemovedSession, TokenType)>, actix_web::Error> {
+        // JWT access tokens can't be revoked; they expire with the access token
+        // of their session, at the latest
+        if token.contains('.') {
+            return Err(OAuthError::UnsupportedTokenType.into());
+        }
+        // unknown tokens need no revocation
+        let (token_type, digest) = match token::parse(token) {
+            Some(parsed) => parsed,
+            None => return Ok(None),
+        };
+
+        let removed = match token_type {
+            TokenType::AccessToken => self.sessions.remove(digest).await?,
+            TokenType::RefreshToken => self.sessions.remove_by_refresh_token(digest).await?,
         };
-        if hours < 0 {
+        Ok(removed.map(|removed| (removed, token_type)))
+    }
+
+    /// account and expiration of the password, once the password is verified
+    pub fn verify_authentication(&self, user: &domain::User) -> Result<(), actix_web::Error> {
+        verify_account(user)?;
+
+        if password_expired(user) {
             return Err(actix_web::error::ErrorUnauthorized(
-                "Parola este învechita; Contactați administratorul",
+                "Parola este învechita; Schimbați parola",
             ));
         }
+        Ok(())
+    }
 
-        if user.account_disabled {
-            return Err(actix_web::error::ErrorUnauthorized(
-                "Utilizator dezactivat; Contactați administratorul",
+    /// account, but not the expiration of the password; for grace logins
+    pub fn verify_account(&self, user: &domain::User) -> Result<(), actix_web::Error> {
+        verify_account(user)
+    }
+
+    pub fn password_expired(&self, user: &domain::User) -> bool {
+        password_expired(user)
+    }
+
+    /// new credentials of `user`, checked against the password policy;
+    /// the current password is verified by the caller, with the lockout of logins
+    pub fn change_password(
+        &self,
+        user: &domain::User,
+        new_password: &str,
+        history: &[domain::PasswordHistoryEntry],
+        language: Language,
+    ) -> Result<domain::PasswordChange, actix_web::Error> {
+        self.check_password_policy(user, new_password, history, language)?;
+        Ok(self.new_credentials(user, new_password))
+    }
+
+    /// change-only flow for users who can't log in because their password has expired;
+    /// the current password is verified by the caller, as on login
+    pub fn change_expired_password(
+        &self,
+        user: &domain::User,
+        new_password: &str,
+        history: &[domain::PasswordHistoryEntry],
+        language: Language,
+    ) -> Result<domain::PasswordChange, actix_web::Error> {
+        verify_account(user)?;
+        if !password_expired(user) {
+            return Err(actix_web::error::ErrorBadRequest(
+                "Parola nu este învechita; Schimbați parola după autentificare",
             ));
         }
+        Ok(self.set_password(user, new_password, history, language)?)
+    }
 
-        if user.date_dismiss.is_some() {
-            return Err(actix_web::error::ErrorUnauthorized(
-                "Ești concediat! Contactați Departamentul de Resurse Umane",
-            ));
+    /// one-time code for the forgot-password flow of `user`: the code for the mail
+    /// and its digest to store
+    pub fn password_reset_code(
+        &self,
+        user: &domain::User,
+    ) -> Result<(String, domain::PasswordResetCode), actix_web::Error> {
+        verify_account(user)?;
+        let (code, digest) = token::generate_reset_code();
+        let reset_code = domain::PasswordResetCode {
+            code: digest.as_ref().to_vec(),
+            personnel_nr: user.personnel_nr,
+            expires: self.password_policy.reset_code_expiration(Utc::now()),
+        };
+        Ok((code, reset_code))
+    }
+
+    /// new credentials of `user`, who has proven access to their mail with a reset code
+    pub fn reset_password(
+        &self,
+        user: &domain::User,
+        new_password: &str,
+        history: &[domain::PasswordHistoryEntry],
+        language: Language,
+    ) -> Result<domain::PasswordChange, actix_web::Error> {
+        verify_account(user)?;
+        Ok(self.set_password(user, new_password, history, language)?)
+    }
+
+    /// new credentials of `user` set by an administrator, checked against the password policy
+    /// only; also for new users, whose `password` is still empty
+    pub fn set_password(
+        &self,
+        user: &domain::User,
+        new_password: &str,
+        history: &[domain::PasswordHistoryEntry],
+        language: Language,
+    ) -> Result<domain::PasswordChange, PasswordPolicyError> {
+        self.check_password_policy(user, new_password, history, language)?;
+        Ok(self.new_credentials(user, new_password))
+    }
+
+    /// random password for `user`, already expired, so that the user must change it
+    /// at the next login; returns the password to hand over and the new credentials
+    pub fn temporary_password(&self, user: &domain::User) -> (String, domain::PasswordChange) {
+        let password = token::generate_temporary_password();
+        let change = domain::PasswordChange {
+            password_expiration_date: Utc::now().date_naive() - Duration::days(1),
+            ..self.new_credentials(user, &password)
+        };
+        (password, change)
+    }
+
+    /// every path which sets a password goes through the policy;
+    /// `history` holds the earlier passwords of the user, besides the current one
+    fn check_password_policy(
+        &self,
+        user: &domain::User,
+        new_password: &str,
+        history: &[domain::PasswordHistoryEntry],
+        language: Language,
+    ) -> Result<(), PasswordPolicyError> {
+        let previous: Vec<_> = std::iter::once(user)
+            .filter(|user| !user.password.is_empty())
+            .map(HashedPassword::from)
+            .chain(history.iter().map(HashedPassword::from))
+            .collect();
+        let context = PasswordContext {
+            user,
+            previous: &previous,
+        };
+        self.password_policy.check(new_password, &context, language)
+    }
+
+    /// hash of `password` with the current algorithm and cost, if the stored hash of `user`
+    /// was made with others; `password` must already be verified. The expiration date is kept
+    pub fn rehash_password(
+        &self,
+        user: &domain::User,
+        password: &str,
+    ) -> Option<domain::PasswordChange> {
+        if !self
+            .hasher
+            .needs_rehash(&user.password, user.password_iterations)
+        {
+            return None;
+        }
+        Some(domain::PasswordChange {
+            password_expiration_date: user.password_expiration_date,
+            ..self.new_credentials(user, password)
+        })
+    }
+
+    fn new_credentials(&self, user: &domain::User, new_password: &str) -> domain::PasswordChange {
+        let hashed = self.hasher.hash(new_password);
+        domain::PasswordChange {
+            personnel_nr: user.personnel_nr,
+            salt: hashed.salt,
+            password: hashed.password,
+            password_iterations: hashed.iterations,
+            password_expiration_date: self
+                .password_policy
+                .expiration_date(Utc::now().date_naive()),
         }
-        Ok(())
     }
 
-    fn verify_password(
+    /// end of the lockout of an account after `failures` consecutive failed logins, if any
+    pub fn lockout_after(&self, failures: i32) -> Option<DateTime<Utc>> {
+        self.lockout.locked_until(failures, Utc::now())
+    }
+
+    /// on login, only behind the account lockout
+    pub fn verify_password(
         &self,
-        salt: &str,
-        actual_password: &str,
+        user: &domain::User,
         attempted_password: &str,
     ) -> Result<(), actix_web::Error> {
-        let decoded_salt = decode(salt).unwrap();
-        let decoded_actual_password = decode(actual_password).unwrap();
+        if !self.hasher.verify(
+            &user.salt,
+            &user.password,
+            user.password_iterations,
+            attempted_password,
+        ) {
+            return Err(actix_web::error::ErrorUnauthorized("Parola este incorecta"));
+        }
+        Ok(())
+    }
+}
 
-        pbkdf2::verify(
-            PBKDF2_ALG,
-            self.iterations,
-            decoded_salt.as_slice(),
-            attempted_password.as_bytes(),
-            decoded_actual_password.as_slice(),
-        )
-        .map_err(|_| actix_web::error::ErrorUnauthorized("Parola este incorecta"))
+fn password_expired(user: &domain::User) -> bool {
+    user.password_expiration_date < Utc::now().date_naive()
+}
+
+/// `None` for service clients
+fn password_expires_in_days(auth_info: &AuthenticatedUser) -> Option<i64> {
+    if auth_info.is_service() {
+        return None;
     }
+    let today = Utc::now().date_naive();
+    Some((auth_info.user.password_expiration_date - today).num_days())
+}
 
-    pub fn generate_password_hash(&self, password: &str, salt: &str) -> String {
-        let iterations = NonZeroU32::new(1000).unwrap();
+fn verify_account(user: &domain::User) -> Result<(), actix_web::Error> {
+    if user.account_disabled {
+        return Err(actix_web::error::ErrorUnauthorized(
+            "Utilizator dezactivat; Contactați administratorul",
+        ));
+    }
 
-        log::info!("Credentials len: {:}", CREDENTIAL_LEN);
+    if user.date_dismiss.is_some() {
+        return Err(actix_web::error::ErrorUnauthorized(
+            "Ești concediat! Contactați Departamentul de Resurse Umane",
+        ));
+    }
+    Ok(())
+}
 
-        let decoded_salt = decode(salt).unwrap();
+fn parse_token(token: &str) -> Result<TokenDigest, actix_web::Error> {
+    match token::parse(token) {
+        Some((TokenType::AccessToken, digest)) => Ok(digest),
+        _ => Err(actix_web::error::ErrorBadRequest("invalid auth token")),
+    }
+}
 
-        let mut to_store: Credential = [0u8; CREDENTIAL_LEN];
-        pbkdf2::derive(
-            PBKDF2_ALG,
-            iterations,
-            decoded_salt.as_slice(),
-            password.as_bytes(),
-            &mut to_store,
-        );
-        encode(to_store)
+/// sessions are managed by their users only, not by service clients
+fn session_owner(auth_info: &AuthenticatedUser) -> Result<i16, actix_web::Error> {
+    if auth_info.is_service() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Service clients have no sessions to manage",
+        ));
     }
+    Ok(auth_info.user.personnel_nr)
+}
+
+/// digest of a well-formed reset code
+pub fn parse_reset_code(code: &str) -> Result<TokenDigest, actix_web::Error> {
+    token::parse_reset_code(code).ok_or(actix_web::error::ErrorBadRequest(
+        "Codul de resetare este invalid sau expirat",
+    ))
 }
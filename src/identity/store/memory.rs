@@ -0,0 +1,558 @@
+use chrono::{DateTime, Utc};
+use futures_util::future::{ready, BoxFuture, FutureExt};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+use super::{RemovedSession, Rotation, SessionStore};
+use crate::domain;
+use crate::errors::DatabaseError;
+use crate::identity::{AuthenticatedUser, Expiration, SessionSummary, TokenDigest};
+
+struct Entry {
+    /// current auth token of session
+    token: TokenDigest,
+    /// all refresh tokens issued for session, used ones included
+    refresh_tokens: Vec<TokenDigest>,
+    auth_info: Arc<AuthenticatedUser>,
+}
+
+struct RefreshToken {
+    session_id: Uuid,
+    used: bool,
+}
+
+#[derive(Default)]
+struct Sessions {
+    by_id: HashMap<Uuid, Entry>,
+    ids_by_token: HashMap<TokenDigest, Uuid>,
+    /// sessions of every user, oldest first
+    ids_by_personnel_nr: HashMap<i16, Vec<Uuid>>,
+    refresh_tokens: HashMap<TokenDigest, RefreshToken>,
+}
+
+impl Sessions {
+    fn get(&self, token: &TokenDigest) -> Option<&Arc<AuthenticatedUser>> {
+        let id = self.ids_by_token.get(token)?;
+        self.by_id.get(id).map(|entry| &entry.auth_info)
+    }
+
+    fn remove(&mut self, id: &Uuid) -> Option<Arc<AuthenticatedUser>> {
+        let entry = self.by_id.remove(id)?;
+        self.ids_by_token.remove(&entry.token);
+        for refresh_token in &entry.refresh_tokens {
+            self.refresh_tokens.remove(refresh_token);
+        }
+
+        if let Some(personnel_nr) = entry.auth_info.personnel_nr() {
+            if let Some(ids) = self.ids_by_personnel_nr.get_mut(&personnel_nr) {
+                ids.retain(|it| it != id);
+                if ids.is_empty() {
+                    self.ids_by_personnel_nr.remove(&personnel_nr);
+                }
+            }
+        }
+        Some(entry.auth_info)
+    }
+
+    fn remove_session(&mut self, id: &Uuid) -> Option<RemovedSession> {
+        self.remove(id).map(|auth_info| RemovedSession {
+            id: auth_info.id,
+            personnel_nr: auth_info.personnel_nr(),
+        })
+    }
+
+    /// ids of user sessions matching `filter`
+    fn ids_of<F>(&self, personnel_nr: i16, filter: F) -> Vec<Uuid>
+    where
+        F: Fn(&AuthenticatedUser) -> bool,
+    {
+        let ids = match self.ids_by_personnel_nr.get(&personnel_nr) {
+            Some(ids) => ids,
+            None => return Vec::new(),
+        };
+        ids.iter()
+            .filter(|id| {
+                self.by_id
+                    .get(id)
+                    .is_some_and(|entry| filter(&entry.auth_info))
+            })
+            .copied()
+            .collect()
+    }
+}
+
+/// Sessions kept in process memory; lost on restart
+#[derive(Default)]
+pub struct MemorySessionStore {
+    sessions: RwLock<Sessions>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn insert(
+        &self,
+        token: TokenDigest,
+        refresh_token: Option<TokenDigest>,
+        auth_info: Arc<AuthenticatedUser>,
+        max_sessions: Option<usize>,
+    ) -> BoxFuture<'_, Result<usize, DatabaseError>> {
+        // guard all hashmaps for write
+        let mut guard = self.sessions.write().unwrap();
+        let id = auth_info.id;
+
+        // sessions of service clients belong to no user
+        let evicted: Vec<Uuid> = match auth_info.personnel_nr() {
+            Some(personnel_nr) => {
+                let ids = guard.ids_by_personnel_nr.entry(personnel_nr).or_default();
+                ids.push(id);
+                match max_sessions {
+                    Some(max) if ids.len() > max => ids[..ids.len() - max].to_vec(),
+                    _ => Vec::new(),
+                }
+            }
+            None => Vec::new(),
+        };
+        for evicted_id in &evicted {
+            guard.remove(evicted_id);
+        }
+
+        guard.ids_by_token.insert(token, id);
+        if let Some(refresh_token) = refresh_token {
+            guard.refresh_tokens.insert(
+                refresh_token,
+                RefreshToken {
+                    session_id: id,
+                    used: false,
+                },
+            );
+        }
+        guard.by_id.insert(
+            id,
+            Entry {
+                token,
+                refresh_tokens: refresh_token.into_iter().collect(),
+                auth_info,
+            },
+        );
+
+        ready(Ok(evicted.len())).boxed()
+    }
+
+    fn get(
+        &self,
+        token: TokenDigest,
+    ) -> BoxFuture<'_, Result<Option<Arc<AuthenticatedUser>>, DatabaseError>> {
+        let guard = self.sessions.read().unwrap();
+        let info = guard.get(&token).cloned();
+        ready(Ok(info)).boxed()
+    }
+
+    fn rotate<'a>(
+        &'a self,
+        refresh_token: TokenDigest,
+        token: TokenDigest,
+        new_refresh_token: TokenDigest,
+        client_id: Option<&'a str>,
+        issued: DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<Rotation, DatabaseError>> {
+        let mut guard = self.sessions.write().unwrap();
+
+        let Sessions {
+            by_id,
+            refresh_tokens,
+            ..
+        } = &mut *guard;
+        let id = match refresh_tokens.get_mut(&refresh_token) {
+            None => return ready(Ok(Rotation::Unknown)).boxed(),
+            Some(refresh_token)
+                if by_id
+                    .get(&refresh_token.session_id)
+                    .is_some_and(|entry| entry.auth_info.issued_to.as_deref() != client_id) =>
+            {
+                return ready(Ok(Rotation::WrongClient)).boxed()
+            }
+            Some(used) if used.used => {
+                // replayed refresh token: revoke the whole token family
+                let id = used.session_id;
+                let rotation = match guard.remove(&id) {
+                    Some(auth_info) => Rotation::Reused(auth_info.user.personnel_nr),
+                    None => Rotation::Unknown,
+                };
+                return ready(Ok(rotation)).boxed();
+            }
+            Some(unused) => {
+                unused.used = true;
+                unused.session_id
+            }
+        };
+
+        let Sessions {
+            by_id,
+            ids_by_token,
+            refresh_tokens,
+            ..
+        } = &mut *guard;
+        let entry = match by_id.get_mut(&id) {
+            Some(entry) => entry,
+            None => return ready(Ok(Rotation::Unknown)).boxed(),
+        };
+
+        ids_by_token.remove(&entry.token);
+        ids_by_token.insert(token, id);
+        entry.token = token;
+
+        refresh_tokens.insert(
+            new_refresh_token,
+            RefreshToken {
+                session_id: id,
+                used: false,
+            },
+        );
+        entry.refresh_tokens.push(new_refresh_token);
+
+        *entry.auth_info.token_issued.write().unwrap() = issued;
+
+        ready(Ok(Rotation::Rotated(entry.auth_info.clone()))).boxed()
+    }
+
+    fn sessions_of(
+        &self,
+        personnel_nr: i16,
+        expiration: Expiration,
+    ) -> BoxFuture<'_, Result<Vec<SessionSummary>, DatabaseError>> {
+        let guard = self.sessions.read().unwrap();
+        let sessions = guard
+            .ids_of(personnel_nr, |it| expiration.exceeded_limit(it).is_none())
+            .iter()
+            .filter_map(|id| guard.by_id.get(id))
+            .map(|entry| entry.auth_info.as_ref().into())
+            .collect();
+        ready(Ok(sessions)).boxed()
+    }
+
+    fn set_permissions(
+        &self,
+        personnel_nr: i16,
+        roles: Arc<Vec<domain::UserRole>>,
+        resources: Arc<Vec<domain::UserResource>>,
+    ) -> BoxFuture<'_, Result<usize, DatabaseError>> {
+        let guard = self.sessions.read().unwrap();
+        let ids = guard.ids_of(personnel_nr, |auth_info| !auth_info.is_service());
+        for id in &ids {
+            if let Some(entry) = guard.by_id.get(id) {
+                entry
+                    .auth_info
+                    .set_permissions(roles.clone(), resources.clone());
+            }
+        }
+        ready(Ok(ids.len())).boxed()
+    }
+
+    fn touch(
+        &self,
+        token: TokenDigest,
+        last_seen: DateTime<Utc>,
+        authenticated: Option<DateTime<Utc>>,
+    ) -> BoxFuture<'_, Result<(), DatabaseError>> {
+        let guard = self.sessions.read().unwrap();
+        if let Some(auth_user) = guard.get(&token) {
+            *auth_user.last_seen.write().unwrap() = last_seen;
+            if let Some(authenticated) = authenticated {
+                *auth_user.authenticated.write().unwrap() = authenticated;
+            }
+        }
+        ready(Ok(())).boxed()
+    }
+
+    fn evict_expired(&self, expiration: Expiration) -> BoxFuture<'_, Result<usize, DatabaseError>> {
+        let mut guard = self.sessions.write().unwrap();
+
+        let expired: Vec<Uuid> = guard
+            .by_id
+            .iter()
+            .filter(|(_, entry)| expiration.exceeded_limit(&entry.auth_info).is_some())
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in &expired {
+            guard.remove(id);
+        }
+
+        ready(Ok(expired.len())).boxed()
+    }
+
+    fn remove(
+        &self,
+        token: TokenDigest,
+    ) -> BoxFuture<'_, Result<Option<RemovedSession>, DatabaseError>> {
+        let mut guard = self.sessions.write().unwrap();
+        let removed = guard
+            .ids_by_token
+            .get(&token)
+            .copied()
+            .and_then(|id| guard.remove_session(&id));
+        ready(Ok(removed)).boxed()
+    }
+
+    fn remove_by_refresh_token(
+        &self,
+        refresh_token: TokenDigest,
+    ) -> BoxFuture<'_, Result<Option<RemovedSession>, DatabaseError>> {
+        let mut guard = self.sessions.write().unwrap();
+        let removed = guard
+            .refresh_tokens
+            .get(&refresh_token)
+            .map(|it| it.session_id)
+            .and_then(|id| guard.remove_session(&id));
+        ready(Ok(removed)).boxed()
+    }
+
+    fn remove_by_id(
+        &self,
+        personnel_nr: i16,
+        id: Uuid,
+    ) -> BoxFuture<'_, Result<bool, DatabaseError>> {
+        let mut guard = self.sessions.write().unwrap();
+        let ids = guard.ids_of(personnel_nr, |it| it.id == id);
+        for id in &ids {
+            guard.remove(id);
+        }
+        ready(Ok(!ids.is_empty())).boxed()
+    }
+
+    fn remove_others(
+        &self,
+        personnel_nr: i16,
+        keep_id: Uuid,
+    ) -> BoxFuture<'_, Result<usize, DatabaseError>> {
+        let mut guard = self.sessions.write().unwrap();
+        let ids = guard.ids_of(personnel_nr, |it| it.id != keep_id);
+        for id in &ids {
+            guard.remove(id);
+        }
+        ready(Ok(ids.len())).boxed()
+    }
+
+    fn remove_all(&self, personnel_nr: i16) -> BoxFuture<'_, Result<usize, DatabaseError>> {
+        let mut guard = self.sessions.write().unwrap();
+        let ids = guard.ids_of(personnel_nr, |auth_info| !auth_info.is_service());
+        for id in &ids {
+            guard.remove(id);
+        }
+        ready(Ok(ids.len())).boxed()
+    }
+
+    fn owners(&self) -> BoxFuture<'_, Result<Vec<i16>, DatabaseError>> {
+        let guard = self.sessions.read().unwrap();
+        let owners = guard.ids_by_personnel_nr.keys().copied().collect();
+        ready(Ok(owners)).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::token;
+    use crate::identity::{SessionDevice, TokenType};
+
+    fn session(personnel_nr: i16, issued_to: Option<&str>) -> Arc<AuthenticatedUser> {
+        let user = domain::User {
+            personnel_nr,
+            salt: String::new(),
+            password: String::new(),
+            password_iterations: 0,
+            password_expiration_date: chrono::NaiveDate::MAX,
+            username: "Ion Popescu".to_owned(),
+            account_disabled: false,
+            date_dismiss: None,
+            telefon: None,
+            email: None,
+        };
+        let mut auth_info =
+            AuthenticatedUser::new(user, vec![], vec![], SessionDevice::default(), Utc::now());
+        if let Some(client_id) = issued_to {
+            auth_info = auth_info.into_issued_to(client_id);
+        }
+        Arc::new(auth_info)
+    }
+
+    fn digest(token_type: TokenType) -> TokenDigest {
+        token::generate(token_type).1
+    }
+
+    /// store with one session of user 7; returns its auth and refresh token
+    async fn store_with_session(
+        issued_to: Option<&str>,
+    ) -> (MemorySessionStore, TokenDigest, TokenDigest) {
+        let store = MemorySessionStore::new();
+        let token = digest(TokenType::AccessToken);
+        let refresh_token = digest(TokenType::RefreshToken);
+        store
+            .insert(token, Some(refresh_token), session(7, issued_to), None)
+            .await
+            .unwrap();
+        (store, token, refresh_token)
+    }
+
+    #[actix_web::test]
+    async fn rotation_moves_session_to_new_tokens() {
+        let (store, token, refresh_token) = store_with_session(None).await;
+        let id = store.get(token).await.unwrap().unwrap().id;
+
+        let new_token = digest(TokenType::AccessToken);
+        let new_refresh_token = digest(TokenType::RefreshToken);
+        let rotation = store
+            .rotate(
+                refresh_token,
+                new_token,
+                new_refresh_token,
+                None,
+                Utc::now(),
+            )
+            .await
+            .unwrap();
+        match rotation {
+            Rotation::Rotated(auth_info) => assert_eq!(auth_info.id, id),
+            _ => panic!("expected rotation"),
+        }
+
+        assert!(store.get(token).await.unwrap().is_none());
+        assert_eq!(store.get(new_token).await.unwrap().unwrap().id, id);
+
+        // the new refresh token rotates again
+        let rotation = store
+            .rotate(
+                new_refresh_token,
+                digest(TokenType::AccessToken),
+                digest(TokenType::RefreshToken),
+                None,
+                Utc::now(),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(rotation, Rotation::Rotated(_)));
+    }
+
+    #[actix_web::test]
+    async fn replayed_refresh_token_revokes_session() {
+        let (store, _, refresh_token) = store_with_session(None).await;
+        let new_token = digest(TokenType::AccessToken);
+        let new_refresh_token = digest(TokenType::RefreshToken);
+        store
+            .rotate(
+                refresh_token,
+                new_token,
+                new_refresh_token,
+                None,
+                Utc::now(),
+            )
+            .await
+            .unwrap();
+
+        let rotation = store
+            .rotate(
+                refresh_token,
+                digest(TokenType::AccessToken),
+                digest(TokenType::RefreshToken),
+                None,
+                Utc::now(),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(rotation, Rotation::Reused(7)));
+
+        // the whole token family is gone
+        assert!(store.get(new_token).await.unwrap().is_none());
+        let rotation = store
+            .rotate(
+                new_refresh_token,
+                digest(TokenType::AccessToken),
+                digest(TokenType::RefreshToken),
+                None,
+                Utc::now(),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(rotation, Rotation::Unknown));
+        assert!(store.owners().await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn unknown_refresh_token() {
+        let (store, token, _) = store_with_session(None).await;
+        let rotation = store
+            .rotate(
+                digest(TokenType::RefreshToken),
+                digest(TokenType::AccessToken),
+                digest(TokenType::RefreshToken),
+                None,
+                Utc::now(),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(rotation, Rotation::Unknown));
+        assert!(store.get(token).await.unwrap().is_some());
+    }
+
+    #[actix_web::test]
+    async fn refresh_token_of_other_client_is_rejected() {
+        let (store, token, refresh_token) = store_with_session(Some("app")).await;
+
+        for client_id in [Some("other-app"), None] {
+            let rotation = store
+                .rotate(
+                    refresh_token,
+                    digest(TokenType::AccessToken),
+                    digest(TokenType::RefreshToken),
+                    client_id,
+                    Utc::now(),
+                )
+                .await
+                .unwrap();
+            assert!(matches!(rotation, Rotation::WrongClient));
+        }
+        // nothing changed: the token is still unused
+        assert!(store.get(token).await.unwrap().is_some());
+        let rotation = store
+            .rotate(
+                refresh_token,
+                digest(TokenType::AccessToken),
+                digest(TokenType::RefreshToken),
+                Some("app"),
+                Utc::now(),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(rotation, Rotation::Rotated(_)));
+    }
+
+    #[actix_web::test]
+    async fn session_limit_evicts_oldest_first() {
+        let store = MemorySessionStore::new();
+        let tokens: Vec<TokenDigest> = (0..3).map(|_| digest(TokenType::AccessToken)).collect();
+        for token in &tokens {
+            let evicted = store.insert(*token, None, session(7, None), Some(2)).await;
+            assert!(evicted.unwrap() <= 1);
+        }
+        // sessions of other users don't count
+        store
+            .insert(
+                digest(TokenType::AccessToken),
+                None,
+                session(8, None),
+                Some(2),
+            )
+            .await
+            .unwrap();
+
+        assert!(store.get(tokens[0]).await.unwrap().is_none());
+        assert!(store.get(tokens[1]).await.unwrap().is_some());
+        assert!(store.get(tokens[2]).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,131 @@
+mod memory;
+mod postgres;
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use uuid::Uuid;
+
+use super::{AuthenticatedUser, Expiration, SessionSummary, TokenDigest};
+use crate::domain;
+use crate::errors::DatabaseError;
+
+/// Result of refresh token rotation
+pub enum Rotation {
+    /// session now uses the new tokens
+    Rotated(Arc<AuthenticatedUser>),
+    /// refresh token was already used; the session of this user is removed
+    Reused(i16),
+    /// refresh token was issued to another oauth client; nothing changed
+    WrongClient,
+    /// no such refresh token
+    Unknown,
+}
+
+/// Session removed by one of its tokens
+pub struct RemovedSession {
+    pub id: Uuid,
+    /// none for sessions of service clients
+    pub personnel_nr: Option<i16>,
+}
+
+/// Storage of authenticated sessions, keyed by auth token
+pub trait SessionStore: Send + Sync {
+    /// stores `auth_info` under `token` as a new session with its first refresh token, if any;
+    /// if the user has more than `max_sessions` sessions, the oldest are removed.
+    /// returns count of removed sessions
+    fn insert(
+        &self,
+        token: TokenDigest,
+        refresh_token: Option<TokenDigest>,
+        auth_info: Arc<AuthenticatedUser>,
+        max_sessions: Option<usize>,
+    ) -> BoxFuture<'_, Result<usize, DatabaseError>>;
+
+    /// find session by auth token
+    fn get(
+        &self,
+        token: TokenDigest,
+    ) -> BoxFuture<'_, Result<Option<Arc<AuthenticatedUser>>, DatabaseError>>;
+
+    /// exchange an unused refresh token for a new auth token and refresh token
+    /// of the same session, issued at `issued`;
+    /// a refresh token used the second time removes the whole session.
+    /// `client_id` has to match the oauth client the session was issued to, if any
+    fn rotate<'a>(
+        &'a self,
+        refresh_token: TokenDigest,
+        token: TokenDigest,
+        new_refresh_token: TokenDigest,
+        client_id: Option<&'a str>,
+        issued: DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<Rotation, DatabaseError>>;
+
+    /// active sessions of user, oldest first
+    fn sessions_of(
+        &self,
+        personnel_nr: i16,
+        expiration: Expiration,
+    ) -> BoxFuture<'_, Result<Vec<SessionSummary>, DatabaseError>>;
+
+    /// replace roles and resources in all live sessions of user;
+    /// returns count of updated sessions
+    fn set_permissions(
+        &self,
+        personnel_nr: i16,
+        roles: Arc<Vec<domain::UserRole>>,
+        resources: Arc<Vec<domain::UserResource>>,
+    ) -> BoxFuture<'_, Result<usize, DatabaseError>>;
+
+    /// record activity on session: set last seen timestamp
+    /// and, if given, the renewed auth timestamp
+    fn touch(
+        &self,
+        token: TokenDigest,
+        last_seen: DateTime<Utc>,
+        authenticated: Option<DateTime<Utc>>,
+    ) -> BoxFuture<'_, Result<(), DatabaseError>>;
+
+    /// remove all expired sessions;
+    /// returns count of removed sessions
+    fn evict_expired(&self, expiration: Expiration) -> BoxFuture<'_, Result<usize, DatabaseError>>;
+
+    /// remove session by auth token
+    fn remove(
+        &self,
+        token: TokenDigest,
+    ) -> BoxFuture<'_, Result<Option<RemovedSession>, DatabaseError>>;
+
+    /// remove session by one of its refresh tokens
+    fn remove_by_refresh_token(
+        &self,
+        refresh_token: TokenDigest,
+    ) -> BoxFuture<'_, Result<Option<RemovedSession>, DatabaseError>>;
+
+    /// remove session of user by session id;
+    /// returns false if user has no such session
+    fn remove_by_id(
+        &self,
+        personnel_nr: i16,
+        id: Uuid,
+    ) -> BoxFuture<'_, Result<bool, DatabaseError>>;
+
+    /// remove all sessions of user except `keep_id`;
+    /// returns count of removed sessions
+    fn remove_others(
+        &self,
+        personnel_nr: i16,
+        keep_id: Uuid,
+    ) -> BoxFuture<'_, Result<usize, DatabaseError>>;
+
+    /// remove all sessions of user;
+    /// returns count of removed sessions
+    fn remove_all(&self, personnel_nr: i16) -> BoxFuture<'_, Result<usize, DatabaseError>>;
+
+    /// users with at least one session
+    fn owners(&self) -> BoxFuture<'_, Result<Vec<i16>, DatabaseError>>;
+}
+
+pub use memory::MemorySessionStore;
+pub use postgres::PgSessionStore;
@@ -0,0 +1,316 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Client, Pool};
+use futures_util::future::{ready, BoxFuture, FutureExt};
+use futures_util::try_join;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::{RemovedSession, Rotation, SessionStore};
+use crate::database::{
+    delete_expired_sessions, delete_oldest_sessions, delete_other_sessions_of_user, delete_session,
+    delete_session_by_id, delete_session_by_refresh_token, delete_session_of_user,
+    delete_sessions_of_user, find_active_sessions_of_user, find_oauth_client,
+    find_refresh_token_session, find_session, find_session_owners, find_user_by_name,
+    insert_refresh_token, insert_session, load_client_resources, load_client_roles,
+    load_user_resources, load_user_roles, rekey_session, touch_session, use_refresh_token,
+};
+use crate::domain;
+use crate::errors::DatabaseError;
+use crate::identity::{AuthenticatedUser, Expiration, SessionDevice, SessionSummary, TokenDigest};
+
+/// Sessions kept in `security.sessions`; survive restarts
+/// and are shared between server instances
+pub struct PgSessionStore {
+    pool: Pool,
+}
+
+impl PgSessionStore {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+/// session with current user, roles and resources
+async fn load_session(
+    client: &Client,
+    token: TokenDigest,
+) -> Result<Option<Arc<AuthenticatedUser>>, DatabaseError> {
+    let session = match find_session(client, token.as_ref()).await? {
+        Some(session) => session,
+        None => return Ok(None),
+    };
+
+    let device = SessionDevice {
+        user_agent: session.user_agent,
+        client_ip: session.client_ip,
+    };
+
+    let mut auth_info = match (session.client_id, session.personnel_nr) {
+        (Some(client_id), _) => {
+            let oauth_client = find_oauth_client(client, &client_id);
+            let roles = load_client_roles(client, &client_id);
+            let resources = load_client_resources(client, &client_id);
+            let (oauth_client, roles, resources) = try_join!(oauth_client, roles, resources)?;
+
+            // client may be disabled meanwhile
+            let oauth_client = match oauth_client {
+                Some(oauth_client) if !oauth_client.disabled => oauth_client,
+                _ => return Ok(None),
+            };
+            AuthenticatedUser::service(&oauth_client, roles, resources, device, session.created)
+        }
+        (None, Some(personnel_nr)) => {
+            let user = find_user_by_name(client, personnel_nr);
+            let roles = load_user_roles(client, personnel_nr);
+            let resources = load_user_resources(client, personnel_nr);
+            let (user, roles, resources) = try_join!(user, roles, resources)?;
+
+            // user may be deleted, disabled or dismissed meanwhile
+            let user = match user {
+                Some(user) if !user.account_disabled && user.date_dismiss.is_none() => user,
+                _ => return Ok(None),
+            };
+            AuthenticatedUser::new(user, roles, resources, device, session.created)
+        }
+        (None, None) => return Ok(None),
+    };
+    auth_info.id = session.id;
+    auth_info.restricted = session.restricted;
+    auth_info.issued_to = session.issued_to;
+    *auth_info.authenticated.write().unwrap() = session.authenticated;
+    *auth_info.last_seen.write().unwrap() = session.last_seen;
+    *auth_info.token_issued.write().unwrap() = session.token_issued;
+    Ok(Some(Arc::new(auth_info)))
+}
+
+impl SessionStore for PgSessionStore {
+    fn insert(
+        &self,
+        token: TokenDigest,
+        refresh_token: Option<TokenDigest>,
+        auth_info: Arc<AuthenticatedUser>,
+        max_sessions: Option<usize>,
+    ) -> BoxFuture<'_, Result<usize, DatabaseError>> {
+        async move {
+            let client = self.pool.get().await.map_err(DatabaseError::PoolError)?;
+
+            let personnel_nr = auth_info.personnel_nr();
+            let session = domain::Session {
+                id: auth_info.id,
+                token: token.as_ref().to_vec(),
+                personnel_nr,
+                created: auth_info.created,
+                authenticated: *auth_info.authenticated.read().unwrap(),
+                last_seen: *auth_info.last_seen.read().unwrap(),
+                user_agent: auth_info.device.user_agent.clone(),
+                client_ip: auth_info.device.client_ip.clone(),
+                token_issued: *auth_info.token_issued.read().unwrap(),
+                client_id: auth_info.client_id.clone(),
+                restricted: auth_info.restricted,
+                issued_to: auth_info.issued_to.clone(),
+            };
+            insert_session(&client, &session).await?;
+            if let Some(refresh_token) = refresh_token {
+                insert_refresh_token(&client, refresh_token.as_ref(), session.id).await?;
+            }
+
+            let evicted = match (max_sessions, personnel_nr) {
+                (Some(max), Some(personnel_nr)) => {
+                    delete_oldest_sessions(&client, personnel_nr, max as i64).await?
+                }
+                _ => 0,
+            };
+            Ok(evicted as usize)
+        }
+        .boxed()
+    }
+
+    fn get(
+        &self,
+        token: TokenDigest,
+    ) -> BoxFuture<'_, Result<Option<Arc<AuthenticatedUser>>, DatabaseError>> {
+        async move {
+            let client = self.pool.get().await.map_err(DatabaseError::PoolError)?;
+            load_session(&client, token).await
+        }
+        .boxed()
+    }
+
+    fn rotate<'a>(
+        &'a self,
+        refresh_token: TokenDigest,
+        token: TokenDigest,
+        new_refresh_token: TokenDigest,
+        client_id: Option<&'a str>,
+        issued: DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<Rotation, DatabaseError>> {
+        async move {
+            let client = self.pool.get().await.map_err(DatabaseError::PoolError)?;
+
+            let id = match find_refresh_token_session(&client, refresh_token.as_ref()).await? {
+                Some((_, issued_to)) if issued_to.as_deref() != client_id => {
+                    return Ok(Rotation::WrongClient)
+                }
+                Some((id, _)) => id,
+                None => return Ok(Rotation::Unknown),
+            };
+
+            if !use_refresh_token(&client, refresh_token.as_ref()).await? {
+                // replayed refresh token: revoke the whole token family
+                let rotation = match delete_session_by_id(&client, id).await? {
+                    Some(personnel_nr) => Rotation::Reused(personnel_nr),
+                    None => Rotation::Unknown,
+                };
+                return Ok(rotation);
+            }
+
+            if !rekey_session(&client, id, token.as_ref(), issued).await? {
+                return Ok(Rotation::Unknown);
+            }
+            insert_refresh_token(&client, new_refresh_token.as_ref(), id).await?;
+
+            let rotation = match load_session(&client, token).await? {
+                Some(auth_info) => Rotation::Rotated(auth_info),
+                None => Rotation::Unknown,
+            };
+            Ok(rotation)
+        }
+        .boxed()
+    }
+
+    fn sessions_of(
+        &self,
+        personnel_nr: i16,
+        expiration: Expiration,
+    ) -> BoxFuture<'_, Result<Vec<SessionSummary>, DatabaseError>> {
+        async move {
+            let client = self.pool.get().await.map_err(DatabaseError::PoolError)?;
+            let sessions = find_active_sessions_of_user(
+                &client,
+                personnel_nr,
+                expiration.authenticated_before,
+                expiration.last_seen_before,
+            )
+            .await?;
+
+            let sessions = sessions
+                .into_iter()
+                .map(|session| SessionSummary {
+                    id: session.id,
+                    created: session.created,
+                    last_seen: session.last_seen,
+                    device: SessionDevice {
+                        user_agent: session.user_agent,
+                        client_ip: session.client_ip,
+                    },
+                    current: false,
+                })
+                .collect();
+            Ok(sessions)
+        }
+        .boxed()
+    }
+
+    fn set_permissions(
+        &self,
+        _personnel_nr: i16,
+        _roles: Arc<Vec<domain::UserRole>>,
+        _resources: Arc<Vec<domain::UserResource>>,
+    ) -> BoxFuture<'_, Result<usize, DatabaseError>> {
+        // permissions are loaded with every lookup of a session
+        ready(Ok(0)).boxed()
+    }
+
+    fn touch(
+        &self,
+        token: TokenDigest,
+        last_seen: DateTime<Utc>,
+        authenticated: Option<DateTime<Utc>>,
+    ) -> BoxFuture<'_, Result<(), DatabaseError>> {
+        async move {
+            let client = self.pool.get().await.map_err(DatabaseError::PoolError)?;
+            touch_session(&client, token.as_ref(), last_seen, authenticated).await
+        }
+        .boxed()
+    }
+
+    fn evict_expired(&self, expiration: Expiration) -> BoxFuture<'_, Result<usize, DatabaseError>> {
+        async move {
+            let client = self.pool.get().await.map_err(DatabaseError::PoolError)?;
+            let count = delete_expired_sessions(
+                &client,
+                expiration.authenticated_before,
+                expiration.last_seen_before,
+            )
+            .await?;
+            Ok(count as usize)
+        }
+        .boxed()
+    }
+
+    fn remove(
+        &self,
+        token: TokenDigest,
+    ) -> BoxFuture<'_, Result<Option<RemovedSession>, DatabaseError>> {
+        async move {
+            let client = self.pool.get().await.map_err(DatabaseError::PoolError)?;
+            let removed = delete_session(&client, token.as_ref()).await?;
+            Ok(removed.map(|(id, personnel_nr)| RemovedSession { id, personnel_nr }))
+        }
+        .boxed()
+    }
+
+    fn remove_by_refresh_token(
+        &self,
+        refresh_token: TokenDigest,
+    ) -> BoxFuture<'_, Result<Option<RemovedSession>, DatabaseError>> {
+        async move {
+            let client = self.pool.get().await.map_err(DatabaseError::PoolError)?;
+            let removed = delete_session_by_refresh_token(&client, refresh_token.as_ref()).await?;
+            Ok(removed.map(|(id, personnel_nr)| RemovedSession { id, personnel_nr }))
+        }
+        .boxed()
+    }
+
+    fn remove_by_id(
+        &self,
+        personnel_nr: i16,
+        id: Uuid,
+    ) -> BoxFuture<'_, Result<bool, DatabaseError>> {
+        async move {
+            let client = self.pool.get().await.map_err(DatabaseError::PoolError)?;
+            delete_session_of_user(&client, personnel_nr, id).await
+        }
+        .boxed()
+    }
+
+    fn remove_others(
+        &self,
+        personnel_nr: i16,
+        keep_id: Uuid,
+    ) -> BoxFuture<'_, Result<usize, DatabaseError>> {
+        async move {
+            let client = self.pool.get().await.map_err(DatabaseError::PoolError)?;
+            let count = delete_other_sessions_of_user(&client, personnel_nr, keep_id).await?;
+            Ok(count as usize)
+        }
+        .boxed()
+    }
+
+    fn remove_all(&self, personnel_nr: i16) -> BoxFuture<'_, Result<usize, DatabaseError>> {
+        async move {
+            let client = self.pool.get().await.map_err(DatabaseError::PoolError)?;
+            let count = delete_sessions_of_user(&client, personnel_nr).await?;
+            Ok(count as usize)
+        }
+        .boxed()
+    }
+
+    fn owners(&self) -> BoxFuture<'_, Result<Vec<i16>, DatabaseError>> {
+        async move {
+            let client = self.pool.get().await.map_err(DatabaseError::PoolError)?;
+            find_session_owners(&client).await
+        }
+        .boxed()
+    }
+}
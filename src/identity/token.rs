@@ -0,0 +1,250 @@
+use std::hash::{Hash, Hasher};
+
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use ring::constant_time::verify_slices_are_equal;
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use super::TokenType;
+
+/// random part of a token, in bytes
+const SECRET_LEN: usize = 32;
+/// base64url length of the random part
+const ENCODED_SECRET_LEN: usize = 43;
+/// base64url length of the checksum: 4 bytes
+const CHECKSUM_LEN: usize = 6;
+
+/// SHA-256 digest of a token; the only form of a token kept by session stores
+#[derive(Clone, Copy, Debug, Eq)]
+pub struct TokenDigest([u8; digest::SHA256_OUTPUT_LEN]);
+
+impl TokenDigest {
+    fn of(token: &str) -> Self {
+        let mut digest = [0u8; digest::SHA256_OUTPUT_LEN];
+        digest.copy_from_slice(digest::digest(&digest::SHA256, token.as_bytes()).as_ref());
+        Self(digest)
+    }
+}
+
+impl AsRef<[u8]> for TokenDigest {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+// compared in constant time, so that lookups reveal nothing about stored digests
+impl PartialEq for TokenDigest {
+    fn eq(&self, other: &Self) -> bool {
+        verify_slices_are_equal(&self.0, &other.0).is_ok()
+    }
+}
+
+impl Hash for TokenDigest {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl TokenType {
+    /// recognizable by secret scanners
+    fn prefix(&self) -> &'static str {
+        match self {
+            TokenType::AccessToken => "isat_",
+            TokenType::RefreshToken => "isrt_",
+        }
+    }
+}
+
+fn checksum(prefix: &str, secret: &str) -> String {
+    let mut context = digest::Context::new(&digest::SHA256);
+    context.update(prefix.as_bytes());
+    context.update(secret.as_bytes());
+    encode_config(&context.finish().as_ref()[..4], URL_SAFE_NO_PAD)
+}
+
+/// prefix of password reset codes
+const RESET_CODE_PREFIX: &str = "isrc_";
+
+/// new token: prefix, 256 bits from the system CSPRNG and a checksum;
+/// returns the token for the client and its digest for the store
+pub fn generate(token_type: TokenType) -> (String, TokenDigest) {
+    generate_with_prefix(token_type.prefix())
+}
+
+/// new one-time code of the forgot-password flow, in the format of tokens
+pub fn generate_reset_code() -> (String, TokenDigest) {
+    generate_with_prefix(RESET_CODE_PREFIX)
+}
+
+fn generate_with_prefix(prefix: &str) -> (String, TokenDigest) {
+    let mut secret = [0u8; SECRET_LEN];
+    SystemRandom::new()
+        .fill(&mut secret)
+        .expect("system random generator failed");
+    let secret = encode_config(secret, URL_SAFE_NO_PAD);
+
+    let token = format!("{}{}{}", prefix, secret, checksum(prefix, &secret));
+    let digest = TokenDigest::of(&token);
+    (token, digest)
+}
+
+/// random part of temporary passwords, in bytes
+const TEMPORARY_PASSWORD_LEN: usize = 15;
+
+/// random base64url password, set by administrators and changed at the next login
+pub fn generate_temporary_password() -> String {
+    let mut secret = [0u8; TEMPORARY_PASSWORD_LEN];
+    SystemRandom::new()
+        .fill(&mut secret)
+        .expect("system random generator failed");
+    encode_config(secret, URL_SAFE_NO_PAD)
+}
+
+/// type and digest of a well-formed token; `None` for anything else
+pub fn parse(token: &str) -> Option<(TokenType, TokenDigest)> {
+    let token_type = [TokenType::AccessToken, TokenType::RefreshToken]
+        .into_iter()
+        .find(|token_type| token.starts_with(token_type.prefix()))?;
+
+    let digest = parse_with_prefix(token, token_type.prefix())?;
+    Some((token_type, digest))
+}
+
+/// `text` with the secrets of reset codes in it replaced, for logging
+pub fn redact_reset_codes(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(RESET_CODE_PREFIX) {
+        let (before, code) = rest.split_at(start + RESET_CODE_PREFIX.len());
+        redacted.push_str(before);
+        redacted.push_str("[redacted]");
+        rest = code.trim_start_matches(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+/// digest of a well-formed reset code
+pub fn parse_reset_code(code: &str) -> Option<TokenDigest> {
+    parse_with_prefix(code, RESET_CODE_PREFIX)
+}
+
+fn parse_with_prefix(token: &str, prefix: &str) -> Option<TokenDigest> {
+    let rest = token.strip_prefix(prefix)?;
+    if rest.len() != ENCODED_SECRET_LEN + CHECKSUM_LEN || !rest.is_ascii() {
+        return None;
+    }
+    let (secret, check) = rest.split_at(ENCODED_SECRET_LEN);
+    if checksum(prefix, secret) != check {
+        return None;
+    }
+    Some(TokenDigest::of(token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `token` with its last character replaced
+    fn tampered(token: &str) -> String {
+        let (rest, last) = token.split_at(token.len() - 1);
+        let replacement = if last == "A" { "B" } else { "A" };
+        format!("{}{}", rest, replacement)
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        for token_type in [TokenType::AccessToken, TokenType::RefreshToken] {
+            let (token, digest) = generate(token_type);
+            assert!(token.starts_with(token_type.prefix()));
+            assert_eq!(
+                token.len(),
+                token_type.prefix().len() + ENCODED_SECRET_LEN + CHECKSUM_LEN
+            );
+
+            let (parsed_type, parsed_digest) = parse(&token).expect("token parses");
+            assert!(parsed_type == token_type);
+            assert_eq!(parsed_digest, digest);
+        }
+    }
+
+    #[test]
+    fn reset_codes_round_trip() {
+        let (code, digest) = generate_reset_code();
+        assert_eq!(parse_reset_code(&code), Some(digest));
+        assert!(parse(&code).is_none());
+    }
+
+    #[test]
+    fn bad_checksum_is_rejected() {
+        let (token, _) = generate(TokenType::AccessToken);
+        assert!(parse(&tampered(&token)).is_none());
+
+        let (code, _) = generate_reset_code();
+        assert!(parse_reset_code(&tampered(&code)).is_none());
+    }
+
+    #[test]
+    fn bad_prefix_is_rejected() {
+        let (token, _) = generate(TokenType::AccessToken);
+        let secret = token.strip_prefix("isat_").unwrap();
+
+        // the checksum covers the prefix
+        assert!(parse(&format!("isrt_{}", secret)).is_none());
+        assert!(parse_reset_code(&format!("isrc_{}", secret)).is_none());
+        assert!(parse(&format!("xxxx_{}", secret)).is_none());
+        assert!(parse(secret).is_none());
+        assert!(parse_reset_code(&token).is_none());
+    }
+
+    #[test]
+    fn bad_length_is_rejected() {
+        let (token, _) = generate(TokenType::RefreshToken);
+        assert!(parse(&token[..token.len() - 1]).is_none());
+        assert!(parse(&format!("{}A", token)).is_none());
+        assert!(parse("isrt_").is_none());
+        assert!(parse("").is_none());
+    }
+
+    #[test]
+    fn reset_codes_are_redacted() {
+        let (code, _) = generate_reset_code();
+        assert_eq!(redact_reset_codes(&code), "isrc_[redacted]");
+
+        let body = format!(
+            "Cod: {}\nLink: https://example.com/reset?code={}\nValabil 30 minute.",
+            code, code
+        );
+        assert_eq!(
+            redact_reset_codes(&body),
+            "Cod: isrc_[redacted]\nLink: https://example.com/reset?code=isrc_[redacted]\n\
+            Valabil 30 minute."
+        );
+    }
+
+    #[test]
+    fn all_reset_codes_are_redacted() {
+        let (first, _) = generate_reset_code();
+        let (second, _) = generate_reset_code();
+        let body = format!("({}) [{}] {}.", first, second, first);
+        assert_eq!(
+            redact_reset_codes(&body),
+            "(isrc_[redacted]) [isrc_[redacted]] isrc_[redacted]."
+        );
+        assert_eq!(redact_reset_codes("no codes here"), "no codes here");
+    }
+
+    #[test]
+    fn digests_compare_by_value() {
+        let (token, digest) = generate(TokenType::AccessToken);
+        let (_, other) = generate(TokenType::AccessToken);
+
+        assert_eq!(TokenDigest::of(&token), digest);
+        assert_ne!(digest, other);
+        assert_ne!(TokenDigest::of(&tampered(&token)), digest);
+        assert_eq!(
+            digest.as_ref(),
+            digest::digest(&digest::SHA256, token.as_bytes()).as_ref()
+        );
+    }
+}
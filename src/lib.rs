@@ -0,0 +1,10 @@
+pub mod database;
+pub mod domain;
+pub mod dto;
+pub mod errors;
+pub mod handlers;
+pub mod identity;
+pub mod locale;
+pub mod mail;
+pub mod oauth;
+pub mod setup;
@@ -0,0 +1,42 @@
+use actix_web::dev::Payload;
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::{FromRequest, HttpRequest};
+use futures_util::future::{ready, Ready};
+
+/// Language of messages meant for end users, from `Accept-Language`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    Ro,
+    En,
+}
+
+impl Language {
+    /// first supported language of an `Accept-Language` header, in the order given by the client
+    pub fn negotiate(accept_language: &str) -> Option<Self> {
+        accept_language
+            .split(',')
+            .filter_map(|range| range.split(';').next())
+            .filter_map(|tag| tag.trim().split('-').next())
+            .find_map(|primary| match primary.to_ascii_lowercase().as_str() {
+                "ro" => Some(Language::Ro),
+                "en" => Some(Language::En),
+                _ => None,
+            })
+    }
+}
+
+impl FromRequest for Language {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let language = req
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|it| it.to_str().ok())
+            .and_then(Language::negotiate)
+            .unwrap_or_default();
+        ready(Ok(language))
+    }
+}
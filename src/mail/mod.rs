@@ -0,0 +1,23 @@
+mod outbox;
+mod sender;
+
+use chrono::{DateTime, Utc};
+
+pub use outbox::spawn_outbox_dispatcher;
+pub use sender::{FileMailSender, LogMailSender, MailSender, SmtpMailSender};
+
+pub const PASSWORD_RESET_SUBJECT: &str = "Resetarea parolei";
+
+/// text of the forgot-password mail; with `link`, the code is appended to it
+pub fn password_reset_body(code: &str, link: &str, expires: DateTime<Utc>) -> String {
+    let mut body = format!("Codul pentru resetarea parolei: {}\n", code);
+    if !link.is_empty() {
+        body.push_str(&format!("Setați parola nouă la: {}{}\n", link, code));
+    }
+    body.push_str(&format!(
+        "Codul poate fi folosit o singură dată, până la {} UTC.\n\
+        Dacă nu ați cerut resetarea parolei, ignorați acest mesaj.\n",
+        expires.format("%Y-%m-%d %H:%M")
+    ));
+    body
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::rt;
+use chrono::Utc;
+use deadpool_postgres::Pool;
+
+use super::MailSender;
+use crate::database::{
+    claim_outbox_mail, clear_expired_outbox_mail, mark_outbox_mail_failed, mark_outbox_mail_sent,
+};
+use crate::errors::DatabaseError;
+
+/// mails taken from the outbox at once
+const BATCH_SIZE: i64 = 50;
+
+/// periodically sends unsent mail from the outbox;
+/// failed mail is retried after `retry_after`, up to `max_attempts` times
+pub fn spawn_outbox_dispatcher(
+    pool: Pool,
+    sender: Arc<dyn MailSender>,
+    period: Duration,
+    retry_after: chrono::Duration,
+    max_attempts: i32,
+) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(period);
+        loop {
+            interval.tick().await;
+            match dispatch(&pool, sender.as_ref(), retry_after, max_attempts).await {
+                Ok(0) => {}
+                Ok(sent) => log::info!("sent {} mails from outbox", sent),
+                Err(err) => log::error!("failed to read mail outbox: {}", err),
+            }
+        }
+    });
+}
+
+/// returns count of sent mails; bodies of expired mail are cleared first
+async fn dispatch(
+    pool: &Pool,
+    sender: &dyn MailSender,
+    retry_after: chrono::Duration,
+    max_attempts: i32,
+) -> Result<usize, DatabaseError> {
+    let client = pool.get().await.map_err(DatabaseError::PoolError)?;
+    let expired = clear_expired_outbox_mail(&client).await?;
+    if expired > 0 {
+        log::info!("cleared {} expired mails in outbox", expired);
+    }
+    let mails =
+        claim_outbox_mail(&client, max_attempts, Utc::now() + retry_after, BATCH_SIZE).await?;
+
+    let mut sent = 0;
+    for mail in mails {
+        match sender.send(&mail).await {
+            Ok(()) => {
+                mark_outbox_mail_sent(&client, mail.id).await?;
+                sent += 1;
+            }
+            Err(err) => {
+                if mail.attempts >= max_attempts {
+                    log::error!("giving up mail {} to {}: {}", mail.id, mail.recipient, err);
+                } else {
+                    log::warn!(
+                        "failed to send mail {} to {}: {}",
+                        mail.id,
+                        mail.recipient,
+                        err
+                    );
+                }
+                mark_outbox_mail_failed(&client, mail.id, &err.to_string()).await?;
+            }
+        }
+    }
+    Ok(sent)
+}
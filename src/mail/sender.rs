@@ -0,0 +1,108 @@
+use futures_util::future::{BoxFuture, FutureExt};
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::domain;
+use crate::errors::MailError;
+use crate::identity::redact_reset_codes;
+use crate::setup::{MailConfig, SmtpTls};
+
+/// Delivery of mail taken from the outbox
+pub trait MailSender: Send + Sync {
+    fn send<'a>(&'a self, mail: &'a domain::OutboxMail) -> BoxFuture<'a, Result<(), MailError>>;
+}
+
+fn message(from: &Mailbox, mail: &domain::OutboxMail) -> Result<Message, MailError> {
+    let message = Message::builder()
+        .from(from.clone())
+        .to(mail.recipient.parse()?)
+        .subject(&mail.subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(mail.body.clone())?;
+    Ok(message)
+}
+
+/// Writes mail to the log instead of sending it, without the secrets of reset codes
+pub struct LogMailSender;
+
+impl MailSender for LogMailSender {
+    fn send<'a>(&'a self, mail: &'a domain::OutboxMail) -> BoxFuture<'a, Result<(), MailError>> {
+        log::info!(
+            "mail {} to {}: {}\n{}",
+            mail.id,
+            mail.recipient,
+            mail.subject,
+            redact_reset_codes(&mail.body)
+        );
+        futures_util::future::ready(Ok(())).boxed()
+    }
+}
+
+/// Writes each mail to an `.eml` file in a directory
+pub struct FileMailSender {
+    from: Mailbox,
+    transport: AsyncFileTransport<Tokio1Executor>,
+}
+
+impl FileMailSender {
+    pub fn new(from: &str, path: &str) -> Result<Self, MailError> {
+        std::fs::create_dir_all(path)?;
+        Ok(Self {
+            from: from.parse()?,
+            transport: AsyncFileTransport::new(path),
+        })
+    }
+}
+
+impl MailSender for FileMailSender {
+    fn send<'a>(&'a self, mail: &'a domain::OutboxMail) -> BoxFuture<'a, Result<(), MailError>> {
+        async move {
+            let id = self.transport.send(message(&self.from, mail)?).await?;
+            log::debug!("mail {} written to {}.eml", mail.id, id);
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Sends mail through an SMTP relay
+pub struct SmtpMailSender {
+    from: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailSender {
+    pub fn new(config: &MailConfig) -> Result<Self, MailError> {
+        let tls = match config.smtp_tls {
+            SmtpTls::StartTls => Tls::Required(TlsParameters::new(config.smtp_host.clone())?),
+            SmtpTls::Tls => Tls::Wrapper(TlsParameters::new(config.smtp_host.clone())?),
+            SmtpTls::None => Tls::None,
+        };
+        let mut builder =
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(config.smtp_host.as_str())
+                .port(config.smtp_port)
+                .tls(tls);
+        if !config.smtp_username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                config.smtp_username.clone(),
+                config.smtp_password.clone(),
+            ));
+        }
+        Ok(Self {
+            from: config.from.parse()?,
+            transport: builder.build(),
+        })
+    }
+}
+
+impl MailSender for SmtpMailSender {
+    fn send<'a>(&'a self, mail: &'a domain::OutboxMail) -> BoxFuture<'a, Result<(), MailError>> {
+        async move {
+            self.transport.send(message(&self.from, mail)?).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
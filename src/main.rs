@@ -1,13 +1,6 @@
-mod database;
-mod domain;
-mod dto;
-mod errors;
-mod handlers;
-mod identity;
-mod setup;
-
 use actix_web::{middleware::Logger, web, App, HttpServer};
-use dotenv::dotenv;
+use identity_server_rs::{handlers, identity, mail, oauth, setup};
+use std::time::Duration;
 
 // TODO: need use variables in testing/test-remote.http
 
@@ -17,19 +10,56 @@ async fn main() -> std::io::Result<()> {
     // std::env::set_var("RUST_BACKTRACE", "1");
     // env_logger::init();
     env_logger::init_from_env(::env_logger::Env::default().default_filter_or("info"));
-    dotenv().ok();
-
-    let config_ = ::config::Config::builder()
-        .add_source(::config::Environment::default())
-        .build()
-        .unwrap();
 
-    let config: setup::ServerConfig = config_.try_deserialize().unwrap();
+    let config = setup::load_config();
     let ssl_builder = setup::ssl(&config.ssl);
 
+    let listener_db_config = setup::listener_db_config(&config.pg);
     let pool = setup::create_db_pool(config.pg);
-    let identity_service = identity::Identity::new();
+    let session_store = setup::create_session_store(&config.session, pool.clone());
+    let session_policy = identity::SessionPolicy::new(&config.session);
+    let jwt_issuer = setup::jwt_issuer(&config.jwt);
+    let password_hasher = identity::PasswordHasher::new(&config.password);
+    let identity_service = identity::Identity::new(
+        session_store,
+        session_policy,
+        jwt_issuer,
+        config.admin_role.clone(),
+        identity::PasswordPolicy::new(&config.password, &password_hasher),
+        password_hasher,
+        identity::LockoutPolicy::new(&config.lockout),
+    );
+    identity::spawn_session_reaper(
+        identity_service.clone(),
+        Duration::from_secs(config.session.reaper_interval_secs),
+    );
+    if config.session.listen_changes {
+        identity::spawn_change_listener(
+            identity_service.clone(),
+            pool.clone(),
+            listener_db_config,
+            setup::tls_connector(),
+        );
+    }
+
+    mail::spawn_outbox_dispatcher(
+        pool.clone(),
+        setup::create_mail_sender(&config.mail),
+        Duration::from_secs(config.mail.dispatch_interval_secs),
+        chrono::Duration::seconds(config.mail.retry_after_secs),
+        config.mail.max_attempts,
+    );
+
     let auth_token_middleware_factory = identity::AuthTokenMiddlewareFactory::new();
+    let rate_limiter = identity::RateLimiter::new(&config.rate_limit)
+        .limit("/login")
+        .limit("/password/expired")
+        .limit("/password/forgot")
+        .limit("/password/reset")
+        .limit("/auth/password")
+        .limit("/token/refresh")
+        .limit("/authorize")
+        .limit("/token");
 
     log::info!("Server running at http://{}/", config.server_addr);
 
@@ -38,11 +68,26 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .app_data(web::Data::new(pool.clone()))
             .app_data(web::Data::new(identity_service.clone()))
+            .app_data(web::Data::new(config.oauth.clone()))
+            .app_data(web::Data::new(config.password.clone()))
             .wrap(logger)
             .wrap(auth_token_middleware_factory.clone())
+            .wrap(rate_limiter.clone())
             .service(handlers::hello)
             .service(handlers::login)
+            .service(handlers::refresh_token)
             .service(handlers::logout)
+            .service(handlers::change_expired_password)
+            .service(handlers::forgot_password)
+            .service(handlers::reset_password)
+            .service(handlers::jwks)
+            .service(oauth::authorize_form)
+            .service(oauth::authorize)
+            .service(oauth::token)
+            .service(oauth::openid_configuration)
+            .service(oauth::userinfo)
+            .service(oauth::introspect)
+            .service(oauth::revoke)
             .service(handlers::auth_scope())
     })
     .bind_openssl(config.server_addr.clone(), ssl_builder)?
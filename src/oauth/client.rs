@@ -0,0 +1,54 @@
+use actix_web::{http::header, HttpRequest};
+use base64::decode;
+use deadpool_postgres::Client;
+
+use crate::database::find_oauth_client;
+use crate::domain;
+use crate::errors::OAuthError;
+use crate::identity::Identity;
+
+/// Client id and secret, from HTTP Basic authentication
+/// or from request body (RFC 6749 section 2.3.1)
+struct ClientCredentials {
+    client_id: String,
+    client_secret: String,
+}
+
+impl ClientCredentials {
+    fn from_basic(req: &HttpRequest) -> Option<Self> {
+        let auth_header = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+        let encoded = auth_header.strip_prefix("Basic ")?;
+        let decoded = String::from_utf8(decode(encoded.trim()).ok()?).ok()?;
+        let (client_id, client_secret) = decoded.split_once(':')?;
+        Some(Self {
+            client_id: client_id.to_owned(),
+            client_secret: client_secret.to_owned(),
+        })
+    }
+}
+
+/// authenticate the registered client calling an oauth endpoint
+pub async fn authenticate_client(
+    req: &HttpRequest,
+    db_client: &Client,
+    identity: &Identity,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<domain::OAuthClient, actix_web::Error> {
+    let credentials = match (ClientCredentials::from_basic(req), client_id, client_secret) {
+        (Some(credentials), _, _) => credentials,
+        (None, Some(client_id), Some(client_secret)) => ClientCredentials {
+            client_id: client_id.to_owned(),
+            client_secret: client_secret.to_owned(),
+        },
+        _ => return Err(OAuthError::InvalidClient.into()),
+    };
+
+    let client = find_oauth_client(db_client, &credentials.client_id)
+        .await?
+        .ok_or(OAuthError::InvalidClient)?;
+
+    identity.verify_client_secret(&client, &credentials.client_secret)?;
+
+    Ok(client)
+}
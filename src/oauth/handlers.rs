@@ -0,0 +1,561 @@
+use actix_web::http::{header, StatusCode};
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder, Result};
+use chrono::{Duration, Utc};
+use deadpool_postgres::{Client, Pool};
+use futures_util::try_join;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::client::authenticate_client;
+use super::{login_page, pkce};
+use crate::database::{
+    find_oauth_client, find_oauth_client_redirect_uris, find_user_by_name,
+    insert_authorization_code, insert_token_revocation, load_client_resources, load_client_roles,
+    load_user_resources, load_user_roles, use_authorization_code,
+};
+use crate::domain;
+use crate::errors::{DatabaseError, OAuthError};
+use crate::handlers::{upgrade_password_hash, verify_password_with_lockout};
+use crate::identity::{
+    AuthTokenContext, AuthenticationResponse, Identity, SessionDevice, TokenInfo,
+};
+use crate::setup::OAuthConfig;
+
+/// RFC 6749 section 4.1.1, with PKCE parameters of RFC 7636
+#[derive(Deserialize)]
+pub struct AuthorizationRequest {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub state: Option<String>,
+    pub scope: Option<String>,
+    /// OpenID Connect
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AuthorizationLogin {
+    #[serde(flatten)]
+    request: AuthorizationRequest,
+    username: String,
+    password: String,
+}
+
+#[derive(Serialize)]
+struct AuthorizationGranted<'a> {
+    code: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<&'a str>,
+}
+
+/// RFC 6749 section 4.1.2.1
+#[derive(Serialize)]
+struct AuthorizationDenied<'a> {
+    error: &'static str,
+    error_description: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<&'a str>,
+}
+
+fn redirect<T: Serialize>(redirect_uri: &str, params: &T) -> HttpResponse {
+    let query = serde_urlencoded::to_string(params).unwrap_or_default();
+    let separator = if redirect_uri.contains('?') { '&' } else { '?' };
+    HttpResponse::Found()
+        .insert_header((
+            header::LOCATION,
+            format!("{}{}{}", redirect_uri, separator, query),
+        ))
+        .finish()
+}
+
+/// client and redirect URI are checked before anything is redirected back;
+/// with unknown ones the error is shown to the user instead
+async fn authorization_client(
+    db_client: &Client,
+    request: &AuthorizationRequest,
+) -> Result<domain::OAuthClient> {
+    let oauth_client = find_oauth_client(db_client, &request.client_id)
+        .await?
+        .filter(|oauth_client| !oauth_client.disabled)
+        .ok_or(actix_web::error::ErrorBadRequest("Unknown oauth client"))?;
+
+    let redirect_uris = find_oauth_client_redirect_uris(db_client, &request.client_id).await?;
+    if !redirect_uris.contains(&request.redirect_uri) {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Redirect URI is not registered for the oauth client",
+        ));
+    }
+    Ok(oauth_client)
+}
+
+/// OpenID Connect is requested with scope `openid`
+fn is_openid(scope: Option<&str>) -> bool {
+    scope.is_some_and(|scope| scope.split(' ').any(|it| it == "openid"))
+}
+
+/// errors of the request itself, reported to the client by redirect;
+/// ID tokens are JWT, so scope `openid` needs JWT enabled
+fn check_authorization_request(
+    request: &AuthorizationRequest,
+    identity: &Identity,
+) -> Option<HttpResponse> {
+    let (error, error_description) = if request.response_type != "code" {
+        (
+            "unsupported_response_type",
+            "only response_type code is supported",
+        )
+    } else if !request
+        .code_challenge
+        .as_deref()
+        .is_some_and(pkce::is_valid_challenge)
+    {
+        ("invalid_request", "code_challenge is required")
+    } else if request.code_challenge_method.as_deref() != Some(pkce::S256) {
+        ("invalid_request", "code_challenge_method must be S256")
+    } else if is_openid(request.scope.as_deref()) && identity.jwt().is_err() {
+        ("invalid_scope", "scope openid is not supported")
+    } else {
+        return None;
+    };
+
+    let denied = AuthorizationDenied {
+        error,
+        error_description,
+        state: request.state.as_deref(),
+    };
+    Some(redirect(&request.redirect_uri, &denied))
+}
+
+#[get("/authorize")]
+pub async fn authorize_form(
+    db_pool: web::Data<Pool>,
+    identity: web::Data<Identity>,
+    request: web::Query<AuthorizationRequest>,
+) -> Result<HttpResponse> {
+    let client = db_pool.get().await.map_err(DatabaseError::PoolError)?;
+
+    let oauth_client = authorization_client(&client, &request).await?;
+    if let Some(denied) = check_authorization_request(&request, &identity) {
+        return Ok(denied);
+    }
+
+    Ok(login_page::render(
+        StatusCode::OK,
+        &oauth_client.name,
+        &request,
+        None,
+    ))
+}
+
+#[post("/authorize")]
+pub async fn authorize(
+    db_pool: web::Data<Pool>,
+    identity: web::Data<Identity>,
+    oauth_config: web::Data<OAuthConfig>,
+    form: web::Form<AuthorizationLogin>,
+) -> Result<HttpResponse> {
+    let client = db_pool.get().await.map_err(DatabaseError::PoolError)?;
+    let request = &form.request;
+
+    let oauth_client = authorization_client(&client, request).await?;
+    if let Some(denied) = check_authorization_request(request, &identity) {
+        return Ok(denied);
+    }
+
+    // login step, as in `/login`
+    let user = match FromStr::from_str(&form.username) {
+        Ok(personnel_nr) => find_user_by_name(&client, personnel_nr).await?,
+        Err(_) => None,
+    };
+    let user = match user {
+        Some(user) => user,
+        None => {
+            return Ok(login_page::render(
+                StatusCode::UNAUTHORIZED,
+                &oauth_client.name,
+                request,
+                Some("Utilizatorul cu acest nume nu este autentificat"),
+            ))
+        }
+    };
+    let verified =
+        match verify_password_with_lockout(&client, &identity, &user, &form.password).await {
+            Ok(()) => identity.verify_authentication(&user),
+            Err(err) => Err(err),
+        };
+    if let Err(err) = verified {
+        return Ok(login_page::render(
+            err.as_response_error().status_code(),
+            &oauth_client.name,
+            request,
+            Some(&err.to_string()),
+        ));
+    }
+    upgrade_password_hash(&client, &identity, &user, &form.password).await;
+
+    log::info!(
+        "authorized user {} / {} for oauth client {}",
+        &user.username,
+        &user.personnel_nr,
+        &oauth_client.client_id
+    );
+
+    let code = domain::AuthorizationCode {
+        code: Uuid::new_v4(),
+        client_id: oauth_client.client_id,
+        personnel_nr: user.personnel_nr,
+        redirect_uri: request.redirect_uri.clone(),
+        code_challenge: request.code_challenge.clone().unwrap_or_default(),
+        scope: request.scope.clone(),
+        expires: Utc::now() + Duration::seconds(oauth_config.code_lifetime_secs),
+        nonce: request.nonce.clone(),
+    };
+    insert_authorization_code(&client, &code).await?;
+
+    let granted = AuthorizationGranted {
+        code: code.code,
+        state: request.state.as_deref(),
+    };
+    Ok(redirect(&request.redirect_uri, &granted))
+}
+
+/// RFC 6749 section 4.1.3 and 6
+#[derive(Deserialize)]
+pub struct TokenRequest {
+    grant_type: String,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    code_verifier: Option<String>,
+    refresh_token: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// RFC 6749 section 5.1; `access_token` is the session token
+#[derive(Serialize)]
+pub struct TokenResponse {
+    access_token: String,
+    token_type: &'static str,
+    expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+    /// OpenID Connect, with scope `openid`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_token: Option<String>,
+}
+
+impl TokenResponse {
+    fn new(response: AuthenticationResponse, scope: Option<String>) -> Self {
+        Self {
+            access_token: response.token,
+            token_type: "Bearer",
+            expires_in: response.expires_in,
+            refresh_token: response.refresh_token,
+            scope,
+            id_token: None,
+        }
+    }
+}
+
+async fn exchange_authorization_code(
+    req: &HttpRequest,
+    client: &Client,
+    identity: &Identity,
+    oauth_client: &domain::OAuthClient,
+    form: &TokenRequest,
+) -> Result<TokenResponse> {
+    let code = form
+        .code
+        .as_deref()
+        .ok_or(OAuthError::InvalidRequest("code is required"))?;
+    let code_verifier = form
+        .code_verifier
+        .as_deref()
+        .ok_or(OAuthError::InvalidRequest("code_verifier is required"))?;
+
+    let code = Uuid::parse_str(code)
+        .map_err(|_| OAuthError::InvalidGrant("authorization code is invalid or expired"))?;
+    let code = use_authorization_code(client, code, Utc::now())
+        .await?
+        .ok_or(OAuthError::InvalidGrant(
+            "authorization code is invalid or expired",
+        ))?;
+
+    if code.client_id != oauth_client.client_id {
+        return Err(
+            OAuthError::InvalidGrant("authorization code was issued to another client").into(),
+        );
+    }
+    if form.redirect_uri.as_deref() != Some(code.redirect_uri.as_str()) {
+        return Err(OAuthError::InvalidGrant("redirect_uri does not match").into());
+    }
+    if !pkce::verify(code_verifier, &code.code_challenge) {
+        return Err(OAuthError::InvalidGrant("code_verifier does not match code_challenge").into());
+    }
+    // JWT may have been disabled since the code was issued; no session without the ID token
+    let openid = is_openid(code.scope.as_deref());
+    if openid && identity.jwt().is_err() {
+        return Err(OAuthError::InvalidGrant("scope openid is not supported").into());
+    }
+
+    let user = find_user_by_name(client, code.personnel_nr);
+    let roles = load_user_roles(client, code.personnel_nr);
+    let resources = load_user_resources(client, code.personnel_nr);
+    let (user, roles, resources) = try_join!(user, roles, resources)?;
+    let user = user.ok_or(OAuthError::InvalidGrant("user not found"))?;
+
+    let device = SessionDevice::of(req);
+    let response = identity
+        .authenticate(
+            user,
+            roles,
+            resources,
+            device,
+            false,
+            Some(&oauth_client.client_id),
+        )
+        .await?;
+
+    let id_token = if openid {
+        Some(identity.id_token(
+            &response.auth_info,
+            &oauth_client.client_id,
+            code.nonce.as_deref(),
+        )?)
+    } else {
+        None
+    };
+
+    let mut token_response = TokenResponse::new(response, code.scope);
+    token_response.id_token = id_token;
+    Ok(token_response)
+}
+
+#[post("/token")]
+pub async fn token(
+    req: HttpRequest,
+    db_pool: web::Data<Pool>,
+    identity: web::Data<Identity>,
+    form: web::Form<TokenRequest>,
+) -> Result<HttpResponse> {
+    let client = db_pool.get().await.map_err(DatabaseError::PoolError)?;
+
+    let oauth_client = authenticate_client(
+        &req,
+        &client,
+        &identity,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await?;
+
+    let response = match form.grant_type.as_str() {
+        "authorization_code" => {
+            exchange_authorization_code(&req, &client, &identity, &oauth_client, &form).await?
+        }
+        "refresh_token" => {
+            let refresh_token = form
+                .refresh_token
+                .as_deref()
+                .ok_or(OAuthError::InvalidRequest("refresh_token is required"))?;
+            let response = identity
+                .refresh(refresh_token, Some(&oauth_client.client_id))
+                .await?;
+            TokenResponse::new(response, None)
+        }
+        "client_credentials" => {
+            if !oauth_client.service {
+                return Err(OAuthError::UnauthorizedClient.into());
+            }
+            let roles = load_client_roles(&client, &oauth_client.client_id);
+            let resources = load_client_resources(&client, &oauth_client.client_id);
+            let (roles, resources) = try_join!(roles, resources)?;
+
+            log::info!("service client {} authenticated", &oauth_client.client_id);
+
+            let device = SessionDevice::of(&req);
+            let response = identity
+                .authenticate_service(&oauth_client, roles, resources, device)
+                .await?;
+            TokenResponse::new(response, None)
+        }
+        _ => return Err(OAuthError::UnsupportedGrantType.into()),
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .insert_header((header::PRAGMA, "no-cache"))
+        .json(response))
+}
+
+#[derive(Deserialize)]
+pub struct IntrospectionRequest {
+    token: String,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// RFC 7662 introspection response
+#[derive(Serialize)]
+pub struct Introspection {
+    active: bool,
+    #[serde(flatten)]
+    details: Option<IntrospectionDetails>,
+}
+
+#[derive(Serialize)]
+pub struct IntrospectionDetails {
+    token_type: &'static str,
+    iat: i64,
+    exp: i64,
+    sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    personnel_nr: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<String>,
+    username: String,
+    roles: Arc<Vec<domain::UserRole>>,
+    resources: Arc<Vec<domain::UserResource>>,
+}
+
+impl From<Option<TokenInfo>> for Introspection {
+    fn from(info: Option<TokenInfo>) -> Self {
+        let details = info.map(|info| {
+            let auth_info = info.auth_info;
+            IntrospectionDetails {
+                token_type: "Bearer",
+                iat: info.issued_at.timestamp(),
+                exp: info.expires_at.timestamp(),
+                sub: auth_info.subject(),
+                personnel_nr: (!auth_info.is_service()).then_some(auth_info.user.personnel_nr),
+                client_id: auth_info.client_id.clone(),
+                username: auth_info.user.username.clone(),
+                roles: auth_info.roles(),
+                resources: auth_info.resources(),
+            }
+        });
+        Self {
+            active: details.is_some(),
+            details,
+        }
+    }
+}
+
+#[post("/introspect")]
+pub async fn introspect(
+    req: HttpRequest,
+    db_pool: web::Data<Pool>,
+    identity: web::Data<Identity>,
+    form: web::Form<IntrospectionRequest>,
+) -> Result<impl Responder> {
+    let client = db_pool.get().await.map_err(DatabaseError::PoolError)?;
+
+    let oauth_client = authenticate_client(
+        &req,
+        &client,
+        &identity,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await?;
+
+    log::debug!(
+        "token introspection by {} / {}",
+        oauth_client.name,
+        oauth_client.client_id
+    );
+
+    if form.token.is_empty() {
+        return Err(OAuthError::InvalidRequest("token is required").into());
+    }
+
+    let info = identity.introspect(&form.token).await?;
+
+    Ok(web::Json(Introspection::from(info)))
+}
+
+#[derive(Deserialize)]
+pub struct RevocationRequest {
+    token: String,
+    // `token_type_hint` is not needed: the token prefix tells its type
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// RFC 7009 token revocation, for registered clients and administrators
+#[post("/revoke")]
+pub async fn revoke(
+    req: HttpRequest,
+    db_pool: web::Data<Pool>,
+    identity: web::Data<Identity>,
+    token_context: Option<web::ReqData<AuthTokenContext>>,
+    form: web::Form<RevocationRequest>,
+) -> Result<HttpResponse> {
+    let client = db_pool.get().await.map_err(DatabaseError::PoolError)?;
+
+    let revoked_by = match token_context {
+        Some(token_context) if form.client_id.is_none() => {
+            let auth_info = identity.authorization_info(&token_context.token).await?;
+            if auth_info.restricted {
+                return Err(actix_web::error::ErrorForbidden(
+                    "Parola este învechita; Schimbați parola",
+                ));
+            }
+            if !identity.is_admin(&auth_info) {
+                return Err(actix_web::error::ErrorForbidden(
+                    "Only administrators may revoke tokens",
+                ));
+            }
+            format!("admin:{}", auth_info.subject())
+        }
+        _ => {
+            let oauth_client = authenticate_client(
+                &req,
+                &client,
+                &identity,
+                form.client_id.as_deref(),
+                form.client_secret.as_deref(),
+            )
+            .await?;
+            oauth_client.client_id
+        }
+    };
+
+    if form.token.is_empty() {
+        return Err(OAuthError::InvalidRequest("token is required").into());
+    }
+
+    // unknown tokens are answered with 200 as well
+    if let Some((removed, token_type)) = identity.revoke(&form.token).await? {
+        match removed.personnel_nr {
+            Some(personnel_nr) => log::info!(
+                "revoked {} of user {} by {}",
+                token_type.as_str(),
+                personnel_nr,
+                revoked_by
+            ),
+            None => log::info!(
+                "revoked {} of service session {} by {}",
+                token_type.as_str(),
+                removed.id,
+                revoked_by
+            ),
+        }
+
+        let revocation = domain::TokenRevocation {
+            session_id: removed.id,
+            personnel_nr: removed.personnel_nr,
+            token_type: token_type.as_str(),
+            revoked_by,
+        };
+        insert_token_revocation(&client, &revocation).await?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,84 @@
+use actix_web::http::{header, StatusCode};
+use actix_web::HttpResponse;
+
+use super::handlers::AuthorizationRequest;
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn hidden(name: &str, value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!(
+            "<input type=\"hidden\" name=\"{}\" value=\"{}\">\n",
+            name,
+            escape(value)
+        ),
+        None => String::new(),
+    }
+}
+
+/// login step of `/authorize`; posts the authorization request back with the credentials
+pub fn render(
+    status: StatusCode,
+    client_name: &str,
+    request: &AuthorizationRequest,
+    error: Option<&str>,
+) -> HttpResponse {
+    let mut fields = String::new();
+    fields.push_str(&hidden("response_type", Some(&request.response_type)));
+    fields.push_str(&hidden("client_id", Some(&request.client_id)));
+    fields.push_str(&hidden("redirect_uri", Some(&request.redirect_uri)));
+    fields.push_str(&hidden("state", request.state.as_deref()));
+    fields.push_str(&hidden("scope", request.scope.as_deref()));
+    fields.push_str(&hidden("nonce", request.nonce.as_deref()));
+    fields.push_str(&hidden("code_challenge", request.code_challenge.as_deref()));
+    fields.push_str(&hidden(
+        "code_challenge_method",
+        request.code_challenge_method.as_deref(),
+    ));
+
+    let error = match error {
+        Some(error) => format!("<p class=\"error\">{}</p>\n", escape(error)),
+        None => String::new(),
+    };
+
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Autentificare</title>
+</head>
+<body>
+<h1>Autentificare pentru {client}</h1>
+{error}<form method="post" action="/authorize">
+{fields}<label>Număr de personal <input name="username" required autofocus></label>
+<label>Parola <input name="password" type="password" required></label>
+<button type="submit">Intră</button>
+</form>
+</body>
+</html>
+"#,
+        client = escape(client_name),
+        error = error,
+        fields = fields,
+    );
+
+    HttpResponse::build(status)
+        .insert_header((header::CONTENT_TYPE, "text/html; charset=utf-8"))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .insert_header((header::X_FRAME_OPTIONS, "DENY"))
+        .body(body)
+}
@@ -0,0 +1,8 @@
+mod client;
+mod handlers;
+mod login_page;
+mod oidc;
+mod pkce;
+
+pub use handlers::{authorize, authorize_form, introspect, revoke, token};
+pub use oidc::{openid_configuration, userinfo};
@@ -0,0 +1,57 @@
+use actix_web::{get, route, web, HttpResponse, Responder, Result};
+use serde_json::json;
+
+use crate::identity::{AuthTokenContext, Identity, UserClaims};
+
+/// OpenID Connect discovery; `jwt.issuer` must be the public base URL of the server
+#[get("/.well-known/openid-configuration")]
+pub async fn openid_configuration(identity: web::Data<Identity>) -> Result<impl Responder> {
+    let jwt = identity.jwt()?;
+    let base_url = jwt.issuer().trim_end_matches('/');
+
+    Ok(web::Json(json!({
+        "issuer": jwt.issuer(),
+        "authorization_endpoint": format!("{}/authorize", base_url),
+        "token_endpoint": format!("{}/token", base_url),
+        "userinfo_endpoint": format!("{}/userinfo", base_url),
+        "jwks_uri": format!("{}/.well-known/jwks.json", base_url),
+        "introspection_endpoint": format!("{}/introspect", base_url),
+        "revocation_endpoint": format!("{}/revoke", base_url),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code", "refresh_token", "client_credentials"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": [jwt.algorithm()],
+        "scopes_supported": ["openid", "profile", "email", "phone"],
+        "claims_supported": [
+            "sub", "preferred_username", "email", "phone_number", "roles", "auth_time", "nonce"
+        ],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post"],
+        "code_challenge_methods_supported": ["S256"],
+    })))
+}
+
+#[route("/userinfo", method = "GET", method = "POST")]
+pub async fn userinfo(
+    identity: web::Data<Identity>,
+    token_context: Option<web::ReqData<AuthTokenContext>>,
+) -> Result<HttpResponse> {
+    let token_context = token_context.ok_or(actix_web::error::ErrorUnauthorized(
+        "You are not authenticated",
+    ))?;
+    let auth_info = identity.authorization_info(&token_context.token).await?;
+    if auth_info.is_service() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Service clients have no user info",
+        ));
+    }
+    if auth_info.restricted {
+        return Err(actix_web::error::ErrorForbidden(
+            "Parola este învechita; Schimbați parola",
+        ));
+    }
+    identity
+        .record_activity(&token_context.token, &auth_info)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(UserClaims::from(auth_info.as_ref())))
+}
@@ -0,0 +1,70 @@
+use base64::{encode_config, URL_SAFE_NO_PAD};
+use ring::{constant_time::verify_slices_are_equal, digest};
+
+/// the only supported `code_challenge_method`; `plain` gives no protection
+pub const S256: &str = "S256";
+
+/// RFC 7636 section 4.1: 43 to 128 unreserved characters
+fn is_valid_verifier(value: &str) -> bool {
+    (43..=128).contains(&value.len())
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-._~".contains(&b))
+}
+
+/// base64url encoded SHA-256 digest
+pub fn is_valid_challenge(challenge: &str) -> bool {
+    challenge.len() == 43
+        && challenge
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+pub fn verify(code_verifier: &str, code_challenge: &str) -> bool {
+    if !is_valid_verifier(code_verifier) {
+        return false;
+    }
+    let digest = digest::digest(&digest::SHA256, code_verifier.as_bytes());
+    let expected = encode_config(digest.as_ref(), URL_SAFE_NO_PAD);
+    verify_slices_are_equal(expected.as_bytes(), code_challenge.as_bytes()).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 7636 appendix B
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+    #[test]
+    fn rfc_7636_s256_example() {
+        assert!(is_valid_challenge(CHALLENGE));
+        assert!(verify(VERIFIER, CHALLENGE));
+    }
+
+    #[test]
+    fn other_verifier_is_rejected() {
+        let other = VERIFIER.replace('d', "e");
+        assert!(!verify(&other, CHALLENGE));
+        // plain method: the verifier itself as challenge
+        assert!(!verify(VERIFIER, VERIFIER));
+    }
+
+    #[test]
+    fn verifier_length_and_characters() {
+        assert!(!verify(&VERIFIER[..42], CHALLENGE));
+        assert!(is_valid_verifier(&"a".repeat(43)));
+        assert!(is_valid_verifier(&"a".repeat(128)));
+        assert!(!is_valid_verifier(&"a".repeat(129)));
+        assert!(is_valid_verifier(&format!("{}-._~", "a".repeat(40))));
+        assert!(!is_valid_verifier(&format!("{}+/=", "a".repeat(40))));
+    }
+
+    #[test]
+    fn challenge_format() {
+        assert!(!is_valid_challenge(&CHALLENGE[..42]));
+        assert!(!is_valid_challenge(&format!("{}=", CHALLENGE)));
+        assert!(!is_valid_challenge(&CHALLENGE.replace('-', "+")));
+    }
+}
@@ -9,6 +9,418 @@ pub struct ServerConfig {
     pub server_addr: String,
     pub ssl: SSLConfig,
     pub pg: deadpool_postgres::Config,
+    #[serde(default)]
+    pub session: SessionConfig,
+    #[serde(default)]
+    pub jwt: JwtConfig,
+    #[serde(default)]
+    pub oauth: OAuthConfig,
+    #[serde(default)]
+    pub password: PasswordConfig,
+    #[serde(default)]
+    pub mail: MailConfig,
+    #[serde(default)]
+    pub lockout: LockoutConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// role which allows to manage tokens and accounts of other users
+    #[serde(default = "default_admin_role")]
+    pub admin_role: String,
+}
+
+fn default_admin_role() -> String {
+    "admin".to_owned()
+}
+
+/// configuration from the environment and `.env`
+pub fn load_config() -> ServerConfig {
+    dotenv::dotenv().ok();
+
+    let config = ::config::Config::builder()
+        .add_source(::config::Environment::default())
+        .build()
+        .unwrap();
+
+    let config: ServerConfig = config.try_deserialize().unwrap();
+    if let Err(err) = config.validate() {
+        panic!("invalid configuration: {}", err);
+    }
+    config
+}
+
+impl ServerConfig {
+    /// settings which would panic or overflow later, while serving requests
+    pub fn validate(&self) -> Result<(), String> {
+        self.session
+            .validate()
+            .map_err(|err| format!("session: {}", err))?;
+        self.password
+            .validate()
+            .map_err(|err| format!("password: {}", err))?;
+        self.lockout
+            .validate()
+            .map_err(|err| format!("lockout: {}", err))?;
+        self.mail
+            .validate()
+            .map_err(|err| format!("mail: {}", err))?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    pub store: SessionStoreKind,
+    /// how often expired sessions are removed from the store
+    pub reaper_interval_secs: u64,
+    /// absolute session lifetime, counted from login
+    pub max_lifetime_minutes: i64,
+    /// session expires after this time without requests; 0 disables
+    pub idle_timeout_minutes: i64,
+    /// renew session lifetime on activity, once half of it has passed
+    pub sliding_renewal: bool,
+    /// concurrent sessions of one user, oldest are closed first; 0 disables
+    pub max_sessions_per_user: usize,
+    /// lifetime of auth token, renewed with refresh token; 0 = as long as session
+    pub access_token_minutes: i64,
+    /// revoke sessions and reload permissions on notifications from database triggers
+    pub listen_changes: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            store: SessionStoreKind::default(),
+            reaper_interval_secs: 300,
+            max_lifetime_minutes: 12 * 60,
+            idle_timeout_minutes: 0,
+            sliding_renewal: false,
+            max_sessions_per_user: 5,
+            access_token_minutes: 15,
+            listen_changes: true,
+        }
+    }
+}
+
+/// longest session and token lifetimes, a year
+const MAX_SESSION_MINUTES: i64 = 365 * 24 * 60;
+
+impl SessionConfig {
+    /// lifetimes out of range can't be subtracted from dates; the reaper needs a period
+    pub fn validate(&self) -> Result<(), String> {
+        if self.reaper_interval_secs == 0 {
+            return Err("reaper_interval_secs must not be 0".to_owned());
+        }
+        if !(1..=MAX_SESSION_MINUTES).contains(&self.max_lifetime_minutes) {
+            return Err(format!(
+                "max_lifetime_minutes must be between 1 and {}",
+                MAX_SESSION_MINUTES
+            ));
+        }
+        if !(0..=MAX_SESSION_MINUTES).contains(&self.idle_timeout_minutes) {
+            return Err(format!(
+                "idle_timeout_minutes must be between 0 and {}",
+                MAX_SESSION_MINUTES
+            ));
+        }
+        if !(0..=MAX_SESSION_MINUTES).contains(&self.access_token_minutes) {
+            return Err(format!(
+                "access_token_minutes must be between 0 and {}",
+                MAX_SESSION_MINUTES
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// signed JWT access tokens, issued in addition to session tokens
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct JwtConfig {
+    pub enabled: bool,
+    pub path: String,
+    /// RSA or EC P-256 private key in PEM
+    pub keyfile: String,
+    /// `iss` of issued tokens; the public base URL of the server for OpenID Connect
+    pub issuer: String,
+    /// lifetime of id tokens; access tokens expire with the access token of their session,
+    /// if it is sooner
+    pub lifetime_minutes: i64,
+}
+
+/// Password changes
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PasswordConfig {
+    pub min_length: usize,
+    /// required count of character classes: lowercase, uppercase, digits, other symbols
+    pub character_classes: usize,
+    /// file of breached or common passwords, one per line; empty disables the check
+    pub breached_list: String,
+    /// earlier passwords which can't be set again, besides the current one
+    pub history_depth: usize,
+    /// logins after the password has expired, with sessions which may only change it;
+    /// 0 rejects logins with an expired password
+    pub grace_logins: i32,
+    /// a changed password expires after this many days
+    pub expiration_days: i64,
+    /// forgot-password codes must be used within this time
+    pub reset_code_minutes: i64,
+    /// page which sets the new password; the code is appended to it in the mail.
+    /// empty: only the code is sent
+    pub reset_link: String,
+    /// algorithm of new hashes; hashes of other algorithms or costs are upgraded on login
+    pub algorithm: PasswordAlgorithm,
+    /// PBKDF2 cost of new hashes, with the `pbkdf2` algorithm
+    pub pbkdf2_iterations: u32,
+    /// Argon2id memory cost, in KiB
+    pub argon2_memory_kib: u32,
+    /// Argon2id passes over the memory
+    pub argon2_iterations: u32,
+    /// Argon2id lanes
+    pub argon2_parallelism: u32,
+}
+
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            character_classes: 3,
+            breached_list: String::new(),
+            history_depth: 5,
+            grace_logins: 3,
+            expiration_days: 90,
+            reset_code_minutes: 30,
+            reset_link: String::new(),
+            algorithm: PasswordAlgorithm::default(),
+            pbkdf2_iterations: 600_000,
+            argon2_memory_kib: 19 * 1024,
+            argon2_iterations: 2,
+            argon2_parallelism: 1,
+        }
+    }
+}
+
+/// longest password lifetime, 100 years
+const MAX_PASSWORD_EXPIRATION_DAYS: i64 = 36_500;
+/// longest lifetime of forgot-password codes, a week
+const MAX_RESET_CODE_MINUTES: i64 = 7 * 24 * 60;
+
+impl PasswordConfig {
+    /// lifetimes out of range can't be added to dates
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_PASSWORD_EXPIRATION_DAYS).contains(&self.expiration_days) {
+            return Err(format!(
+                "expiration_days must be between 1 and {}",
+                MAX_PASSWORD_EXPIRATION_DAYS
+            ));
+        }
+        if !(1..=MAX_RESET_CODE_MINUTES).contains(&self.reset_code_minutes) {
+            return Err(format!(
+                "reset_code_minutes must be between 1 and {}",
+                MAX_RESET_CODE_MINUTES
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// how new password hashes are made
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PasswordAlgorithm {
+    /// PHC strings in `password`
+    #[default]
+    Argon2id,
+    /// base64 PBKDF2-SHA256 hash with a separate salt, as before
+    Pbkdf2,
+}
+
+/// Temporary lockout of accounts after consecutive failed logins
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LockoutConfig {
+    /// failed logins which lock the account; 0 disables the lockout
+    pub threshold: i32,
+    /// first lockout; doubled with every further failed login
+    pub base_lockout_secs: i64,
+    /// longest lockout
+    pub max_lockout_secs: i64,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 5,
+            base_lockout_secs: 60,
+            max_lockout_secs: 60 * 60,
+        }
+    }
+}
+
+/// longest lockout, a year
+const MAX_LOCKOUT_SECS: i64 = 365 * 24 * 60 * 60;
+
+impl LockoutConfig {
+    /// lockouts out of range can't be added to dates
+    pub fn validate(&self) -> Result<(), String> {
+        if self.threshold < 0 {
+            return Err("threshold must not be negative".to_owned());
+        }
+        if !(1..=MAX_LOCKOUT_SECS).contains(&self.base_lockout_secs) {
+            return Err(format!(
+                "base_lockout_secs must be between 1 and {}",
+                MAX_LOCKOUT_SECS
+            ));
+        }
+        if !(self.base_lockout_secs..=MAX_LOCKOUT_SECS).contains(&self.max_lockout_secs) {
+            return Err(format!(
+                "max_lockout_secs must be between base_lockout_secs and {}",
+                MAX_LOCKOUT_SECS
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Token-bucket throttling of login and other credential endpoints
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// requests of one client IP in a burst
+    pub ip_burst: u32,
+    /// requests of one client IP refilled per minute; 0 disables the limit by IP
+    pub ip_per_minute: u32,
+    /// requests for one username in a burst, from any IP
+    pub account_burst: u32,
+    /// requests for one username refilled per minute; 0 disables the limit by account
+    pub account_per_minute: u32,
+    /// comma separated IPs or networks in CIDR notation, such as internal NAT gateways,
+    /// which are not limited by IP; requests from them are still limited by account
+    pub allowlist: String,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            ip_burst: 20,
+            ip_per_minute: 10,
+            account_burst: 5,
+            account_per_minute: 3,
+            allowlist: String::new(),
+        }
+    }
+}
+
+/// Outgoing mail, sent from the outbox
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MailConfig {
+    pub sender: MailSenderKind,
+    /// `From` of sent mail
+    pub from: String,
+    /// how often the outbox is checked for unsent mail
+    pub dispatch_interval_secs: u64,
+    /// failed mail is retried after this time
+    pub retry_after_secs: i64,
+    /// mail is given up after this many failed attempts
+    pub max_attempts: i32,
+    /// directory of `.eml` files for the `file` sender
+    pub path: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_tls: SmtpTls,
+    /// credentials are used when the username is not empty
+    pub smtp_username: String,
+    pub smtp_password: String,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            sender: MailSenderKind::default(),
+            from: "identity-server-rs <noreply@localhost>".to_owned(),
+            dispatch_interval_secs: 10,
+            retry_after_secs: 300,
+            max_attempts: 5,
+            path: "outbox".to_owned(),
+            smtp_host: "localhost".to_owned(),
+            smtp_port: 587,
+            smtp_tls: SmtpTls::default(),
+            smtp_username: String::new(),
+            smtp_password: String::new(),
+        }
+    }
+}
+
+impl MailConfig {
+    /// the outbox dispatcher needs a period
+    pub fn validate(&self) -> Result<(), String> {
+        if self.dispatch_interval_secs == 0 {
+            return Err("dispatch_interval_secs must not be 0".to_owned());
+        }
+        Ok(())
+    }
+}
+
+/// where mail from the outbox goes
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MailSenderKind {
+    /// written to the log, with reset codes redacted; for development and tests
+    Log,
+    /// written to `.eml` files
+    File,
+    #[default]
+    Smtp,
+}
+
+/// encryption of the SMTP connection
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    #[default]
+    StartTls,
+    /// implicit TLS, usually port 465
+    Tls,
+    None,
+}
+
+/// OAuth2 endpoints
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OAuthConfig {
+    /// authorization code must be exchanged within this time
+    pub code_lifetime_secs: i64,
+}
+
+impl Default for OAuthConfig {
+    fn default() -> Self {
+        Self {
+            code_lifetime_secs: 60,
+        }
+    }
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "keystore".to_owned(),
+            keyfile: "internal-devel.key".to_owned(),
+            issuer: "identity-server-rs".to_owned(),
+            lifetime_minutes: 60,
+        }
+    }
+}
+
+/// where authenticated sessions are kept
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    #[default]
+    Memory,
+    Postgres,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -37,9 +449,62 @@ pub fn ssl(config: &SSLConfig) -> SslAcceptorBuilder {
 
 use postgres_openssl::MakeTlsConnector;
 
-pub fn create_db_pool(pg: deadpool_postgres::Config) -> deadpool_postgres::Pool {
+pub fn tls_connector() -> MakeTlsConnector {
     let mut builder = SslConnector::builder(SslMethod::tls()).unwrap();
     builder.set_verify(SslVerifyMode::NONE);
-    let connector = MakeTlsConnector::new(builder.build());
-    pg.create_pool(None, connector).unwrap()
+    MakeTlsConnector::new(builder.build())
+}
+
+pub fn create_db_pool(pg: deadpool_postgres::Config) -> deadpool_postgres::Pool {
+    pg.create_pool(None, tls_connector()).unwrap()
+}
+
+/// config of a dedicated connection, outside of the pool
+pub fn listener_db_config(pg: &deadpool_postgres::Config) -> tokio_postgres::Config {
+    pg.get_pg_config().unwrap()
+}
+
+use crate::identity::{MemorySessionStore, PgSessionStore, SessionStore};
+use std::sync::Arc;
+
+pub fn create_session_store(
+    config: &SessionConfig,
+    pool: deadpool_postgres::Pool,
+) -> Arc<dyn SessionStore> {
+    match config.store {
+        SessionStoreKind::Memory => Arc::new(MemorySessionStore::new()),
+        SessionStoreKind::Postgres => Arc::new(PgSessionStore::new(pool)),
+    }
+}
+
+use crate::mail::{FileMailSender, LogMailSender, MailSender, SmtpMailSender};
+
+pub fn create_mail_sender(config: &MailConfig) -> Arc<dyn MailSender> {
+    match config.sender {
+        MailSenderKind::Log => Arc::new(LogMailSender),
+        MailSenderKind::File => Arc::new(FileMailSender::new(&config.from, &config.path).unwrap()),
+        MailSenderKind::Smtp => Arc::new(SmtpMailSender::new(config).unwrap()),
+    }
+}
+
+use crate::identity::JwtIssuer;
+use openssl::pkey::PKey;
+
+/// load jwt signing key
+pub fn jwt_issuer(config: &JwtConfig) -> Option<JwtIssuer> {
+    if !config.enabled {
+        return None;
+    }
+
+    let keyfilepath = Path::new(&config.path).join(&config.keyfile);
+    let pem = std::fs::read(keyfilepath).unwrap();
+    let key = PKey::private_key_from_pem(&pem).unwrap();
+
+    let issuer = JwtIssuer::new(
+        key,
+        config.issuer.clone(),
+        chrono::Duration::minutes(config.lifetime_minutes),
+    )
+    .unwrap();
+    Some(issuer)
 }